@@ -115,6 +115,7 @@ export default function LiquidatePage() {
       const { getAssociatedTokenAddressSync } = await import('@solana/spl-token');
       const liquidatorCollateralAccount = getAssociatedTokenAddressSync(collateralMintPubkey, publicKey);
       const liquidatorBorrowAccount = getAssociatedTokenAddressSync(borrowMintPubkey, publicKey);
+      const treasuryCollateralAccount = getAssociatedTokenAddressSync(collateralMintPubkey, config.treasury, true);
 
       await program.methods
         .liquidate(amountLamports)
@@ -124,6 +125,7 @@ export default function LiquidatePage() {
           liquidator: publicKey,
           liquidatorCollateralAccount,
           liquidatorBorrowAccount,
+          treasuryCollateralAccount,
           config: configPda,
           vault: vaultPda,
           collateralMint: collateralMintPubkey,
@@ -132,6 +134,13 @@ export default function LiquidatePage() {
           collateralPriceFeed: collateralInfo.priceFeed,
           borrowPriceFeed: borrowInfo.priceFeed,
         })
+        .remainingAccounts(
+          [...config.supportedCollaterals, ...config.supportedBorrows].map((a: any) => ({
+            pubkey: a.priceFeed,
+            isSigner: false,
+            isWritable: false,
+          }))
+        )
         .rpc();
 
       alert('Liquidation successful!');
@@ -26,4 +26,46 @@ pub enum ErrorCode {
     InvalidPrice,
     #[msg("Flashloan repayment failed")]
     FlashReplyError,
+    #[msg("Account is healthy and cannot be liquidated")]
+    AccountHealthy,
+    #[msg("Account still has collateral")]
+    CollateralNotExhausted,
+    #[msg("Insurance unstake cooldown has not elapsed")]
+    CooldownNotElapsed,
+    #[msg("Insurance unstake window has passed, request again")]
+    UnstakeWindowExpired,
+    #[msg("Insufficient insurance shares")]
+    InsufficientShares,
+    #[msg("Stake too small to mint any insurance shares")]
+    ZeroShares,
+    #[msg("Invalid parameter")]
+    InvalidParameter,
+    #[msg("Invalid vault account")]
+    InvalidVault,
+    #[msg("Liquidation would leave the account less healthy")]
+    LiquidationWorsensHealth,
+    #[msg("Seized collateral exceeds repaid value plus bonus")]
+    ExcessiveSeizure,
+    #[msg("Liquidator is the account owner or its delegate")]
+    SelfLiquidation,
+    #[msg("Invalid treasury token account")]
+    InvalidTreasury,
+    #[msg("No matching flash_repay later in the transaction")]
+    FlashRepayMissing,
+    #[msg("Invalid flash borrow/repay instruction")]
+    InvalidFlashInstruction,
+    #[msg("Vault is locked by a flash loan in progress")]
+    VaultLocked,
+    #[msg("Flash loan callback accounts are not allowed")]
+    InvalidCallbackAccounts,
+    #[msg("Flash mint exceeds the asset's ceiling")]
+    FlashMintCeilingExceeded,
+    #[msg("Swap program is not allowed")]
+    SwapProgramNotAllowed,
+    #[msg("Swap result outside of slippage limits")]
+    SlippageExceeded,
+    #[msg("Account is already in the current layout")]
+    AccountAlreadyMigrated,
+    #[msg("Reserves and insurance cannot cover the bad debt")]
+    BadDebtNotCovered,
 }
@@ -0,0 +1,268 @@
+use anchor_lang::prelude::*;
+use crate::state::*;
+use crate::errors::ErrorCode;
+use crate::math::*;
+
+/// Maximum age, in seconds, of a price accepted for health checks.
+pub const PRICE_MAX_AGE: u64 = 60;
+/// A health factor of 1.0. Accounts below this are liquidatable.
+pub const HEALTH_FACTOR_SCALE: u128 = 1_000_000_000;
+
+/// Valuation of a debt account. All values are `amount * price` in
+/// `PRICE_PRECISION` units, the same scale used throughout the program.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct HealthReport {
+    pub collateral_value: u128,
+    /// Collateral value weighted by each asset's `ltv`.
+    pub borrow_limit: u128,
+    /// Collateral value weighted by each asset's `liquidation_threshold`.
+    pub liquidation_limit: u128,
+    pub debt_value: u128,
+    /// `liquidation_limit / debt_value` scaled by `HEALTH_FACTOR_SCALE`,
+    /// `u128::MAX` when the account has no debt.
+    pub health_factor: u128,
+}
+
+impl HealthReport {
+    pub fn is_within_borrow_limit(&self) -> bool {
+        self.debt_value <= self.borrow_limit
+    }
+
+    pub fn is_liquidatable(&self) -> bool {
+        self.debt_value > 0 && self.debt_value >= self.liquidation_limit
+    }
+}
+
+impl HealthReport {
+    pub fn remove_collateral(&mut self, info: &CollateralInfo, amount: u64, price: u64) -> Result<()> {
+        let value = (amount as u128)
+            .checked_mul(price as u128)
+            .ok_or(ErrorCode::MathOverflow)?;
+        // Round the weighted reductions up so the cached limits never exceed
+        // what a full recomputation would produce.
+        self.collateral_value = self.collateral_value.saturating_sub(value);
+        self.borrow_limit = self.borrow_limit
+            .saturating_sub((value * (info.ltv as u128)).div_ceil(10000));
+        self.liquidation_limit = self.liquidation_limit
+            .saturating_sub((value * (info.liquidation_threshold as u128)).div_ceil(10000));
+        self.health_factor = calculate_health_factor(self.liquidation_limit, self.debt_value);
+        Ok(())
+    }
+
+    pub fn add_debt(&mut self, amount: u64, price: u64) -> Result<()> {
+        let value = (amount as u128)
+            .checked_mul(price as u128)
+            .ok_or(ErrorCode::MathOverflow)?;
+        self.debt_value = self.debt_value
+            .checked_add(value)
+            .ok_or(ErrorCode::MathOverflow)?;
+        self.health_factor = calculate_health_factor(self.liquidation_limit, self.debt_value);
+        Ok(())
+    }
+}
+
+/// The cached report, if it was computed in `slot`. Deposits and repays only
+/// update the cache when given every price feed, which otherwise just makes
+/// it conservative; anything that can lower health must either update it or
+/// call `invalidate_cached_health`.
+pub fn cached_health(debt_account: &UserDebtAccount, slot: u64) -> Option<HealthReport> {
+    if debt_account.health_refresh_slot == slot {
+        Some(debt_account.cached_health)
+    } else {
+        None
+    }
+}
+
+pub fn store_cached_health(debt_account: &mut UserDebtAccount, report: HealthReport, slot: u64) {
+    debt_account.cached_health = report;
+    debt_account.health_refresh_slot = slot;
+}
+
+pub fn invalidate_cached_health(debt_account: &mut UserDebtAccount) {
+    debt_account.health_refresh_slot = 0;
+}
+
+/// Starts the liquidation bonus ramp the first time `report` shows the
+/// account liquidatable and clears it once the account has recovered.
+pub fn update_unhealthy_since(debt_account: &mut UserDebtAccount, report: &HealthReport, now: i64) {
+    if !report.is_liquidatable() {
+        debt_account.unhealthy_since_ts = 0;
+    } else if debt_account.unhealthy_since_ts == 0 {
+        debt_account.unhealthy_since_ts = now;
+    }
+}
+
+pub fn find_price_feed<'a, 'info>(
+    feeds: &'a [AccountInfo<'info>],
+    key: &Pubkey,
+) -> Result<&'a AccountInfo<'info>> {
+    feeds
+        .iter()
+        .find(|acc| acc.key == key)
+        .ok_or_else(|| error!(ErrorCode::InvalidPriceFeed))
+}
+
+pub fn calculate_health_factor(liquidation_limit: u128, debt_value: u128) -> u128 {
+    if debt_value == 0 {
+        return u128::MAX;
+    }
+    liquidation_limit
+        .checked_mul(HEALTH_FACTOR_SCALE)
+        .map(|scaled| scaled / debt_value)
+        .unwrap_or(u128::MAX)
+}
+
+/// Values every non-empty position of `debt_account`, pricing each with the
+/// feed configured for its mint and accruing interest up to `now`. `feeds`
+/// must contain those feed accounts; they are matched by key so order does
+/// not matter.
+pub fn compute_health(
+    config: &ProtocolConfig,
+    debt_account: &UserDebtAccount,
+    feeds: &[AccountInfo],
+    now: i64,
+) -> Result<HealthReport> {
+    let mut report = HealthReport::default();
+
+    for c in debt_account.collateral_balances.iter().filter(|c| c.amount > 0) {
+        if let Some(info) = config.supported_collaterals.iter().find(|x| x.mint == c.mint) {
+            let price = get_price_from_feed(find_price_feed(feeds, &info.price_feed)?, PRICE_MAX_AGE, now)?;
+            let value = (c.amount as u128)
+                .checked_mul(price as u128)
+                .ok_or(ErrorCode::MathOverflow)?;
+            report.collateral_value = report.collateral_value
+                .checked_add(value)
+                .ok_or(ErrorCode::MathOverflow)?;
+            report.borrow_limit = report.borrow_limit
+                .checked_add(value * (info.ltv as u128) / 10000)
+                .ok_or(ErrorCode::MathOverflow)?;
+            report.liquidation_limit = report.liquidation_limit
+                .checked_add(value * (info.liquidation_threshold as u128) / 10000)
+                .ok_or(ErrorCode::MathOverflow)?;
+        }
+    }
+
+    for d in debt_account.debt_balances.iter().filter(|d| d.principal > 0) {
+        if let Some(info) = config.supported_borrows.iter().find(|x| x.mint == d.borrow_mint) {
+            let price = get_price_from_feed(find_price_feed(feeds, &info.price_feed)?, PRICE_MAX_AGE, now)?;
+            let index = accrued_global_index(info, now as u64)?;
+            let owed = calculate_owed_amount(d.principal, d.interest_index_snapshot, index)?;
+            let value = (owed as u128)
+                .checked_mul(price as u128)
+                .ok_or(ErrorCode::MathOverflow)?;
+            report.debt_value = report.debt_value
+                .checked_add(value)
+                .ok_or(ErrorCode::MathOverflow)?;
+        }
+    }
+
+    report.health_factor = calculate_health_factor(report.liquidation_limit, report.debt_value);
+    Ok(report)
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug)]
+pub struct AssetCapacity {
+    pub mint: Pubkey,
+    pub amount: u64,
+}
+
+/// Return data of `get_account_health`.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug)]
+pub struct AccountHealth {
+    pub report: HealthReport,
+    /// Additional amount of each borrow asset whose feed was supplied that can
+    /// be borrowed before hitting the borrow limit.
+    pub max_borrowable: Vec<AssetCapacity>,
+    /// Amount of each collateral position that can be withdrawn while staying
+    /// within the borrow limit.
+    pub max_withdrawable: Vec<AssetCapacity>,
+}
+
+pub fn compute_account_health(
+    config: &ProtocolConfig,
+    debt_account: &UserDebtAccount,
+    feeds: &[AccountInfo],
+    now: i64,
+) -> Result<AccountHealth> {
+    let report = compute_health(config, debt_account, feeds, now)?;
+    let headroom = report.borrow_limit.saturating_sub(report.debt_value);
+
+    let mut max_borrowable = Vec::new();
+    for info in &config.supported_borrows {
+        if let Ok(feed) = find_price_feed(feeds, &info.price_feed) {
+            let price = get_price_from_feed(feed, PRICE_MAX_AGE, now)?;
+            max_borrowable.push(AssetCapacity {
+                mint: info.mint,
+                amount: (headroom / price as u128).min(u64::MAX as u128) as u64,
+            });
+        }
+    }
+
+    let mut max_withdrawable = Vec::new();
+    for c in debt_account.collateral_balances.iter().filter(|c| c.amount > 0) {
+        let info = match config.supported_collaterals.iter().find(|x| x.mint == c.mint) {
+            Some(info) => info,
+            None => continue,
+        };
+        let amount = if report.debt_value == 0 || info.ltv == 0 {
+            c.amount
+        } else {
+            let price = get_price_from_feed(find_price_feed(feeds, &info.price_feed)?, PRICE_MAX_AGE, now)?;
+            let value = headroom
+                .checked_mul(10000)
+                .ok_or(ErrorCode::MathOverflow)?
+                / (info.ltv as u128);
+            (value / price as u128).min(c.amount as u128) as u64
+        };
+        max_withdrawable.push(AssetCapacity { mint: c.mint, amount });
+    }
+
+    Ok(AccountHealth { report, max_borrowable, max_withdrawable })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_calculate_health_factor() {
+        assert_eq!(calculate_health_factor(100, 0), u128::MAX);
+        assert_eq!(calculate_health_factor(150, 100), HEALTH_FACTOR_SCALE * 3 / 2);
+        assert_eq!(calculate_health_factor(u128::MAX, 1), u128::MAX);
+    }
+
+    #[test]
+    fn test_liquidatable_threshold() {
+        let mut report = HealthReport {
+            liquidation_limit: 100,
+            debt_value: 99,
+            ..Default::default()
+        };
+        assert!(!report.is_liquidatable());
+        report.debt_value = 100;
+        assert!(report.is_liquidatable());
+        report = HealthReport::default();
+        assert!(!report.is_liquidatable());
+    }
+
+    #[test]
+    fn test_cached_report_updates_are_conservative() {
+        let info = crate::state::test_fixtures::collateral_info(Pubkey::default(), Pubkey::default());
+        let mut report = HealthReport {
+            collateral_value: 1000,
+            borrow_limit: 750,
+            liquidation_limit: 800,
+            debt_value: 0,
+            health_factor: u128::MAX,
+        };
+        report.remove_collateral(&info, 1, 1).unwrap();
+        assert_eq!(report.collateral_value, 999);
+        assert_eq!(report.borrow_limit, 749);
+        assert_eq!(report.liquidation_limit, 799);
+
+        report.add_debt(100, 7).unwrap();
+        assert_eq!(report.debt_value, 700);
+        assert!(report.is_within_borrow_limit());
+        assert_eq!(report.health_factor, calculate_health_factor(799, 700));
+    }
+}
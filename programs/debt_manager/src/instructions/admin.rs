@@ -1,12 +1,18 @@
 use anchor_lang::prelude::*;
 use crate::state::*;
+use crate::errors::ErrorCode;
+
+/// Allocated size of the `ProtocolConfig`.
+pub const CONFIG_SPACE: usize = 8 + 32 + 32 + 4 + (32 + 8 * 3 + 32 + 8 * 2) * 10 + 4 + (32 + 8 * 6 + 16 + 8 + 32 + 8 * 2 + 8 * 2) * 10 + 8 * 5 + 16 + 4 + 32 * crate::instructions::flashloan::MAX_LOCKED_VAULTS
+    + 4 + 32 * crate::instructions::flashloan::MAX_FEE_EXEMPT_PROGRAMS
+    + 4 + 32 * crate::instructions::swap::MAX_SWAP_PROGRAMS + 1;
 
 #[derive(Accounts)]
 pub struct InitializeProtocol<'info> {
     #[account(
         init,
         payer = admin,
-        space = 8 + 32 + 32 + 4 + (32 + 8 + 8 + 8 + 8) * 10 + 4 + (32 + 16 + 16 + 8 + 8) * 10 + 1,
+        space = CONFIG_SPACE,
         seeds = [b"config"],
         bump
     )]
@@ -23,6 +29,159 @@ pub fn initialize_protocol(ctx: Context<InitializeProtocol>) -> Result<()> {
     config.bump = ctx.bumps.config;
     config.supported_collaterals = Vec::new();
     config.supported_borrows = Vec::new();
+    config.liquidation_params = default_liquidation_params();
+    config.locked_vaults = Vec::new();
+    config.fee_exempt_programs = Vec::new();
+    config.swap_programs = Vec::new();
+    Ok(())
+}
+
+fn default_liquidation_params() -> LiquidationParams {
+    LiquidationParams {
+        min_liquidation_bonus: 0,
+        bonus_ramp_duration: 0,
+        close_factor: crate::instructions::liquidation::DEFAULT_CLOSE_FACTOR,
+        full_liquidation_health_factor: crate::instructions::liquidation::DEFAULT_FULL_LIQUIDATION_HEALTH_FACTOR,
+        dust_debt_value: 0,
+        protocol_fee_bps: 0,
+    }
+}
+
+/// `ProtocolConfig` as laid out before liquidation params, flash loan
+/// settings, reserves and the vault and program lists were added. A config in
+/// this layout is smaller than `CONFIG_SPACE` and must go through
+/// `migrate_config`.
+#[derive(AnchorSerialize, AnchorDeserialize)]
+struct LegacyProtocolConfig {
+    admin: Pubkey,
+    treasury: Pubkey,
+    supported_collaterals: Vec<LegacyCollateralInfo>,
+    supported_borrows: Vec<LegacyBorrowAssetInfo>,
+    bump: u8,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize)]
+struct LegacyCollateralInfo {
+    mint: Pubkey,
+    ltv: u64,
+    liquidation_threshold: u64,
+    liquidation_bonus: u64,
+    price_feed: Pubkey,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize)]
+struct LegacyBorrowAssetInfo {
+    mint: Pubkey,
+    base_rate: u64,
+    optimal_utilization: u64,
+    slope1: u64,
+    slope2: u64,
+    total_deposits: u64,
+    total_borrows: u64,
+    global_index: u128,
+    last_update_ts: u64,
+    price_feed: Pubkey,
+}
+
+impl From<LegacyProtocolConfig> for ProtocolConfig {
+    fn from(legacy: LegacyProtocolConfig) -> Self {
+        let supported_collaterals = legacy.supported_collaterals.into_iter()
+            .map(|c| CollateralInfo {
+                mint: c.mint,
+                ltv: c.ltv,
+                liquidation_threshold: c.liquidation_threshold,
+                liquidation_bonus: c.liquidation_bonus,
+                price_feed: c.price_feed,
+                flash_loan_fee_bps: crate::instructions::flashloan::DEFAULT_FLASH_LOAN_FEE_BPS,
+                reserves: 0,
+            })
+            .collect();
+        let supported_borrows = legacy.supported_borrows.into_iter()
+            .map(|b| BorrowAssetInfo {
+                mint: b.mint,
+                base_rate: b.base_rate,
+                optimal_utilization: b.optimal_utilization,
+                slope1: b.slope1,
+                slope2: b.slope2,
+                total_deposits: b.total_deposits,
+                total_borrows: b.total_borrows,
+                global_index: b.global_index,
+                last_update_ts: b.last_update_ts,
+                price_feed: b.price_feed,
+                reserves: 0,
+                bad_debt: 0,
+                flash_loan_fee_bps: crate::instructions::flashloan::DEFAULT_FLASH_LOAN_FEE_BPS,
+                flash_mint_ceiling: 0,
+            })
+            .collect();
+        ProtocolConfig {
+            admin: legacy.admin,
+            treasury: legacy.treasury,
+            supported_collaterals,
+            supported_borrows,
+            liquidation_params: default_liquidation_params(),
+            locked_vaults: Vec::new(),
+            fee_exempt_programs: Vec::new(),
+            swap_programs: Vec::new(),
+            bump: legacy.bump,
+        }
+    }
+}
+
+/// Reads a config account's data in the legacy layout.
+fn read_legacy_config(data: &[u8]) -> Result<LegacyProtocolConfig> {
+    if data.get(..8) != Some(ProtocolConfig::DISCRIMINATOR) {
+        return Err(anchor_lang::error::ErrorCode::AccountDiscriminatorMismatch.into());
+    }
+    Ok(LegacyProtocolConfig::deserialize(&mut &data[8..])?)
+}
+
+#[derive(Accounts)]
+pub struct MigrateConfig<'info> {
+    /// CHECK: Still in the legacy layout, so it cannot be deserialized as a
+    /// `ProtocolConfig`; owner, discriminator and admin are checked in
+    /// instruction
+    #[account(mut, seeds = [b"config"], bump)]
+    pub config: UncheckedAccount<'info>,
+    /// Must be the config's admin; pays the rent for the larger account.
+    #[account(mut)]
+    pub admin: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+/// Grows a config created before the layout change to `CONFIG_SPACE` and
+/// rewrites it with the new fields at their defaults, as
+/// `migrate_debt_account` does for debt accounts.
+pub fn migrate_config(ctx: Context<MigrateConfig>) -> Result<()> {
+    let info = ctx.accounts.config.to_account_info();
+    if info.owner != &crate::ID {
+        return Err(anchor_lang::error::ErrorCode::AccountOwnedByWrongProgram.into());
+    }
+    if info.data_len() >= CONFIG_SPACE {
+        return Err(ErrorCode::AccountAlreadyMigrated.into());
+    }
+    let legacy = read_legacy_config(&info.try_borrow_data()?)?;
+    if legacy.admin != ctx.accounts.admin.key() {
+        return Err(ErrorCode::Unauthorized.into());
+    }
+
+    let rent = Rent::get()?.minimum_balance(CONFIG_SPACE);
+    let shortfall = rent.saturating_sub(info.lamports());
+    if shortfall > 0 {
+        let cpi_accounts = anchor_lang::system_program::Transfer {
+            from: ctx.accounts.admin.to_account_info(),
+            to: info.clone(),
+        };
+        let cpi_ctx = CpiContext::new(ctx.accounts.system_program.to_account_info(), cpi_accounts);
+        anchor_lang::system_program::transfer(cpi_ctx, shortfall)?;
+    }
+    info.resize(CONFIG_SPACE)?;
+
+    let config = ProtocolConfig::from(legacy);
+    let mut data = info.try_borrow_mut_data()?;
+    data.fill(0);
+    let mut writer: &mut [u8] = &mut data;
+    config.try_serialize(&mut writer)?;
     Ok(())
 }
 
@@ -59,6 +218,8 @@ pub fn add_supported_collateral(
         liquidation_threshold,
         liquidation_bonus,
         price_feed,
+        flash_loan_fee_bps: crate::instructions::flashloan::DEFAULT_FLASH_LOAN_FEE_BPS,
+        reserves: 0,
     });
     
     Ok(())
@@ -105,6 +266,10 @@ pub fn add_supported_borrow(
         global_index: crate::math::INDEX_SCALE,
         last_update_ts: clock.unix_timestamp as u64,
         price_feed,
+        reserves: 0,
+        bad_debt: 0,
+        flash_loan_fee_bps: crate::instructions::flashloan::DEFAULT_FLASH_LOAN_FEE_BPS,
+        flash_mint_ceiling: 0,
     });
     
     Ok(())
@@ -174,3 +339,179 @@ pub fn update_rate_model(
     
     Ok(())
 }
+
+#[derive(Accounts)]
+pub struct UpdateLiquidationParams<'info> {
+    #[account(
+        mut,
+        seeds = [b"config"],
+        bump = config.bump,
+        has_one = admin
+    )]
+    pub config: Account<'info, ProtocolConfig>,
+    #[account(mut)]
+    pub admin: Signer<'info>,
+}
+
+pub fn update_liquidation_params(
+    ctx: Context<UpdateLiquidationParams>,
+    params: LiquidationParams,
+) -> Result<()> {
+    if params.min_liquidation_bonus > 10000 || params.close_factor == 0 || params.close_factor > 10000
+        || params.protocol_fee_bps > 10000
+        || params.full_liquidation_health_factor == 0
+        || params.full_liquidation_health_factor as u128 > crate::health::HEALTH_FACTOR_SCALE
+    {
+        return Err(ErrorCode::InvalidParameter.into());
+    }
+    ctx.accounts.config.liquidation_params = params;
+    Ok(())
+}
+
+#[derive(Accounts)]
+pub struct UpdateFlashLoanConfig<'info> {
+    #[account(
+        mut,
+        seeds = [b"config"],
+        bump = config.bump,
+        has_one = admin
+    )]
+    pub config: Account<'info, ProtocolConfig>,
+    #[account(mut)]
+    pub admin: Signer<'info>,
+}
+
+pub fn update_flash_loan_fee(
+    ctx: Context<UpdateFlashLoanConfig>,
+    mint: Pubkey,
+    fee_bps: u64,
+) -> Result<()> {
+    if fee_bps > 10000 {
+        return Err(ErrorCode::InvalidParameter.into());
+    }
+    let config = &mut ctx.accounts.config;
+    if let Some(asset) = config.supported_borrows.iter_mut().find(|b| b.mint == mint) {
+        asset.flash_loan_fee_bps = fee_bps;
+    } else {
+        let collateral = config.supported_collaterals.iter_mut()
+            .find(|c| c.mint == mint)
+            .ok_or(ErrorCode::InvalidVault)?;
+        collateral.flash_loan_fee_bps = fee_bps;
+    }
+    Ok(())
+}
+
+pub fn update_flash_mint_ceiling(
+    ctx: Context<UpdateFlashLoanConfig>,
+    mint: Pubkey,
+    ceiling: u64,
+) -> Result<()> {
+    let asset = ctx.accounts.config.supported_borrows.iter_mut()
+        .find(|b| b.mint == mint)
+        .ok_or(ErrorCode::UnsupportedBorrowAsset)?;
+    asset.flash_mint_ceiling = ceiling;
+    Ok(())
+}
+
+pub fn set_fee_exempt_program(
+    ctx: Context<UpdateFlashLoanConfig>,
+    program: Pubkey,
+    exempt: bool,
+) -> Result<()> {
+    let programs = &mut ctx.accounts.config.fee_exempt_programs;
+    if !exempt {
+        programs.retain(|p| *p != program);
+    } else if !programs.contains(&program) {
+        if programs.len() >= crate::instructions::flashloan::MAX_FEE_EXEMPT_PROGRAMS {
+            return Err(ErrorCode::InvalidParameter.into());
+        }
+        programs.push(program);
+    }
+    Ok(())
+}
+
+#[derive(Accounts)]
+pub struct SetSwapProgram<'info> {
+    #[account(
+        mut,
+        seeds = [b"config"],
+        bump = config.bump,
+        has_one = admin
+    )]
+    pub config: Account<'info, ProtocolConfig>,
+    #[account(mut)]
+    pub admin: Signer<'info>,
+}
+
+pub fn set_swap_program(
+    ctx: Context<SetSwapProgram>,
+    program: Pubkey,
+    allowed: bool,
+) -> Result<()> {
+    let programs = &mut ctx.accounts.config.swap_programs;
+    if !allowed {
+        programs.retain(|p| *p != program);
+    } else if !programs.contains(&program) {
+        if programs.len() >= crate::instructions::swap::MAX_SWAP_PROGRAMS {
+            return Err(ErrorCode::InvalidParameter.into());
+        }
+        programs.push(program);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_legacy_config_migrates_with_defaults() {
+        let mint = Pubkey::new_unique();
+        let legacy = LegacyProtocolConfig {
+            admin: Pubkey::new_unique(),
+            treasury: Pubkey::new_unique(),
+            supported_collaterals: vec![LegacyCollateralInfo {
+                mint,
+                ltv: 7500,
+                liquidation_threshold: 8000,
+                liquidation_bonus: 500,
+                price_feed: Pubkey::new_unique(),
+            }],
+            supported_borrows: vec![LegacyBorrowAssetInfo {
+                mint,
+                base_rate: 100,
+                optimal_utilization: 8000,
+                slope1: 400,
+                slope2: 6000,
+                total_deposits: 1_000,
+                total_borrows: 600,
+                global_index: crate::math::INDEX_SCALE * 2,
+                last_update_ts: 42,
+                price_feed: Pubkey::new_unique(),
+            }],
+            bump: 254,
+        };
+        let mut data = ProtocolConfig::DISCRIMINATOR.to_vec();
+        legacy.serialize(&mut data).unwrap();
+        let config = ProtocolConfig::from(read_legacy_config(&data).unwrap());
+
+        assert_eq!(config.admin, legacy.admin);
+        assert_eq!(config.bump, 254);
+        let collateral = config.supported_collaterals[0];
+        assert_eq!(collateral.liquidation_bonus, 500);
+        assert_eq!(collateral.flash_loan_fee_bps, crate::instructions::flashloan::DEFAULT_FLASH_LOAN_FEE_BPS);
+        assert_eq!(collateral.reserves, 0);
+        let asset = config.supported_borrows[0];
+        assert_eq!(asset.total_borrows, 600);
+        assert_eq!(asset.global_index, crate::math::INDEX_SCALE * 2);
+        assert_eq!(asset.last_update_ts, 42);
+        assert_eq!(asset.flash_mint_ceiling, 0);
+        assert_eq!(config.liquidation_params.close_factor, crate::instructions::liquidation::DEFAULT_CLOSE_FACTOR);
+        assert!(config.locked_vaults.is_empty() && config.swap_programs.is_empty());
+
+        let mut migrated = Vec::new();
+        config.try_serialize(&mut migrated).unwrap();
+        assert!(migrated.len() <= CONFIG_SPACE);
+        assert!(read_legacy_config(&data[..7]).is_err());
+    }
+}
@@ -1,8 +1,45 @@
 use anchor_lang::prelude::*;
-use anchor_spl::token::{self, Mint, Token, TokenAccount, Transfer};
+use anchor_spl::token::{self, Burn, Mint, MintTo, Token, TokenAccount, Transfer};
 use crate::errors::ErrorCode;
+use crate::state::{BorrowAssetInfo, InsuranceFund, ProtocolConfig};
+use crate::instructions::liquidation;
 use anchor_lang::solana_program::instruction::Instruction;
-use anchor_lang::solana_program::program::invoke;
+use anchor_lang::solana_program::program::{invoke, invoke_signed};
+use anchor_lang::solana_program::sysvar::instructions::{self as instructions_sysvar, load_current_index_checked, load_instruction_at_checked};
+
+/// Flash loan fee, in basis points, of newly added borrow assets.
+pub const DEFAULT_FLASH_LOAN_FEE_BPS: u64 = 5;
+/// Most programs that can be exempt from flash loan fees.
+pub const MAX_FEE_EXEMPT_PROGRAMS: usize = 5;
+/// Seed of the PDA that signs `on_flash_loan` callbacks. Receivers check it
+/// is a signer to know the call came from this program.
+pub const FLASH_AUTHORITY_SEED: &[u8] = b"flash_authority";
+/// Anchor discriminator of `on_flash_loan`: `sha256("global:on_flash_loan")[..8]`.
+pub const ON_FLASH_LOAN_DISCRIMINATOR: [u8; 8] = [195, 212, 238, 236, 80, 204, 73, 167];
+/// Anchor discriminator of `on_flash_loan_multi`.
+pub const ON_FLASH_LOAN_MULTI_DISCRIMINATOR: [u8; 8] = [178, 83, 231, 22, 76, 47, 63, 122];
+/// Most vaults that can be locked at once, and so the most loans one
+/// `flash_loan_multi` may take. Bounded by the `locked_vaults` space reserved
+/// in the config account and by the compute the per-leg transfers use.
+pub const MAX_LOCKED_VAULTS: usize = 4;
+/// Position of `vault` in the accounts of `flash_borrow` and `flash_repay`.
+const FLASH_VAULT_INDEX: usize = 2;
+
+/// Arguments of the `on_flash_loan` instruction a flash loan receiver must
+/// implement. It is invoked with the flash authority PDA as its first
+/// account, a signer, followed by the caller's `remaining_accounts`. Before
+/// returning, the receiver must send `amount + fee` of `mint` back to the
+/// vault.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug, PartialEq, Eq)]
+pub struct OnFlashLoan {
+    pub mint: Pubkey,
+    pub amount: u64,
+    pub fee: u64,
+    /// The signer who requested the loan.
+    pub initiator: Pubkey,
+    /// Opaque bytes forwarded from the `flash_loan` caller.
+    pub data: Vec<u8>,
+}
 
 #[derive(Accounts)]
 pub struct FlashLoanAction<'info> {
@@ -20,19 +57,40 @@ pub struct FlashLoanAction<'info> {
     pub token_program: Program<'info, Token>,
     /// CHECK: The program that will be called via CPI
     pub target_program: AccountInfo<'info>,
+    /// CHECK: PDA signing the callback, holds no data
+    #[account(seeds = [FLASH_AUTHORITY_SEED], bump)]
+    pub flash_authority: UncheckedAccount<'info>,
+    #[account(mut, seeds = [b"config"], bump = config.bump)]
+    pub config: Account<'info, ProtocolConfig>,
+    /// Optional. When supplied with its vault, receives the fund's share of
+    /// the fee.
+    #[account(seeds = [b"insurance", borrow_mint.key().as_ref()], bump)]
+    pub insurance_fund: Option<Account<'info, InsuranceFund>>,
+    #[account(mut, seeds = [b"insurance_vault", borrow_mint.key().as_ref()], bump)]
+    pub insurance_vault: Option<Account<'info, TokenAccount>>,
 }
 
-pub fn flash_loan(
-    ctx: Context<FlashLoanAction>, 
+pub fn flash_loan<'info>(
+    ctx: Context<'_, '_, 'info, 'info, FlashLoanAction<'info>>,
     amount: u64,
-    instruction_data: Vec<u8>
+    data: Vec<u8>,
 ) -> Result<()> {
-    // 1. Calculate Fee (e.g. 0.05% = 5 basis points)
-    let fee = amount.checked_mul(5).unwrap() / 10000;
-    let _total_repayment = amount.checked_add(fee).ok_or(ErrorCode::MathOverflow)?;
+    // 1. Calculate Fee
+    let fee = loan_fee(
+        &ctx.accounts.config,
+        &ctx.accounts.borrow_mint.key(),
+        amount,
+        Some(&ctx.accounts.target_program.key()),
+    )?;
 
     let vault_balance_before = ctx.accounts.vault.amount;
 
+    // Lock the vault for the duration of the callback, persisting the lock
+    // before control leaves the program.
+    let vault_key = ctx.accounts.vault.key();
+    lock_vault(&mut ctx.accounts.config, vault_key)?;
+    ctx.accounts.config.exit(&crate::ID)?;
+
     // 2. Transfer Tokens to Borrower
     let mint_key = ctx.accounts.borrow_mint.key();
     let bump = ctx.bumps.vault;
@@ -53,37 +111,671 @@ pub fn flash_loan(
     token::transfer(cpi_ctx, amount)?;
 
     // 3. Execute CPI (Callback)
-    // Construct instruction for the target program.
-    // The remaining accounts are passed to the target program.
-    let mut account_metas = Vec::new();
-    for acc in ctx.remaining_accounts.iter() {
-        account_metas.push(if acc.is_writable {
-            AccountMeta::new(acc.key(), acc.is_signer)
-        } else {
-            AccountMeta::new_readonly(acc.key(), acc.is_signer)
-        });
-    }
-
-    let instruction = Instruction {
-        program_id: ctx.accounts.target_program.key(),
-        accounts: account_metas,
-        data: instruction_data,
+    let args = OnFlashLoan {
+        mint: mint_key,
+        amount,
+        fee,
+        initiator: ctx.accounts.borrower.key(),
+        data,
     };
-
-    invoke(
-        &instruction,
+    invoke_receiver(
+        &ctx.accounts.target_program,
+        &ctx.accounts.flash_authority,
+        ctx.bumps.flash_authority,
         ctx.remaining_accounts,
+        receiver_data(ON_FLASH_LOAN_DISCRIMINATOR, &args)?,
+        &[vault_key],
     )?;
 
     // 4. Verify Repayment
     // We expect the borrower (or the callback logic) to have transferred `total_repayment` back to the vault.
     // We reload the vault account to check the balance.
     ctx.accounts.vault.reload()?;
+    ctx.accounts.config.reload()?;
+    ctx.accounts.config.locked_vaults.retain(|v| *v != vault_key);
     let vault_balance_after = ctx.accounts.vault.amount;
 
-    if vault_balance_after < vault_balance_before.checked_add(fee).unwrap() {
+    if vault_balance_after < vault_balance_before.checked_add(fee).ok_or(ErrorCode::MathOverflow)? {
         return Err(ErrorCode::FlashReplyError.into());
     }
 
+    // 5. Route the insurance fund's share of the fee and account the rest
+    let insured = route_insurance_fee(
+        &ctx.accounts.vault,
+        &ctx.accounts.insurance_fund,
+        &ctx.accounts.insurance_vault,
+        &ctx.accounts.token_program,
+        &mint_key,
+        bump,
+        fee,
+    )?;
+    book_flash_loan_fee(&mut ctx.accounts.config, &mint_key, fee - insured)
+}
+
+fn lock_vault(config: &mut ProtocolConfig, vault: Pubkey) -> Result<()> {
+    check_vault_unlocked(config, &vault)?;
+    if config.locked_vaults.len() >= MAX_LOCKED_VAULTS {
+        return Err(ErrorCode::VaultLocked.into());
+    }
+    config.locked_vaults.push(vault);
+    Ok(())
+}
+
+pub fn check_vault_unlocked(config: &ProtocolConfig, vault: &Pubkey) -> Result<()> {
+    if config.locked_vaults.contains(vault) {
+        return Err(ErrorCode::VaultLocked.into());
+    }
+    Ok(())
+}
+
+pub fn flash_loan_fee(amount: u64, fee_bps: u64) -> Result<u64> {
+    Ok(((amount as u128) * (fee_bps as u128) / 10000) as u64)
+}
+
+/// Fee on a flash loan of `amount` out of the vault of `mint`, at the fee
+/// configured for that borrow asset or collateral, waived when the callback
+/// is a fee-exempt program.
+fn loan_fee(config: &ProtocolConfig, mint: &Pubkey, amount: u64, target_program: Option<&Pubkey>) -> Result<u64> {
+    let fee_bps = vault_fee_bps(config, mint)?;
+    if target_program.is_some_and(|p| config.fee_exempt_programs.contains(p)) {
+        return Ok(0);
+    }
+    flash_loan_fee(amount, fee_bps)
+}
+
+fn vault_fee_bps(config: &ProtocolConfig, mint: &Pubkey) -> Result<u64> {
+    if let Some(asset) = config.supported_borrows.iter().find(|b| b.mint == *mint) {
+        return Ok(asset.flash_loan_fee_bps);
+    }
+    config.supported_collaterals.iter()
+        .find(|c| c.mint == *mint)
+        .map(|c| c.flash_loan_fee_bps)
+        .ok_or_else(|| error!(ErrorCode::InvalidVault))
+}
+
+fn find_borrow_asset<'a>(config: &'a mut ProtocolConfig, mint: &Pubkey) -> Result<&'a mut BorrowAssetInfo> {
+    config.supported_borrows.iter_mut()
+        .find(|b| b.mint == *mint)
+        .ok_or_else(|| error!(ErrorCode::UnsupportedBorrowAsset))
+}
+
+/// Books a flash loan fee left in the vault of `mint` to the reserves of its
+/// borrow asset or collateral.
+fn book_flash_loan_fee(config: &mut ProtocolConfig, mint: &Pubkey, fee: u64) -> Result<()> {
+    if let Some(asset) = config.supported_borrows.iter_mut().find(|b| b.mint == *mint) {
+        return accrue_flash_loan_fee(asset, fee);
+    }
+    let collateral = config.supported_collaterals.iter_mut()
+        .find(|c| c.mint == *mint)
+        .ok_or(ErrorCode::InvalidVault)?;
+    collateral.reserves = collateral.reserves.checked_add(fee).ok_or(ErrorCode::MathOverflow)?;
+    Ok(())
+}
+
+/// Books a flash loan fee left in the vault to the asset's reserves.
+pub fn accrue_flash_loan_fee(asset: &mut BorrowAssetInfo, fee: u64) -> Result<()> {
+    asset.reserves = asset.reserves.checked_add(fee).ok_or(ErrorCode::MathOverflow)?;
+    Ok(())
+}
+
+/// Sends the insurance fund's share of `fee` from the vault, when the fund
+/// and its vault were supplied. Returns the amount sent.
+fn route_insurance_fee<'info>(
+    vault: &Account<'info, TokenAccount>,
+    insurance_fund: &Option<Account<'info, InsuranceFund>>,
+    insurance_vault: &Option<Account<'info, TokenAccount>>,
+    token_program: &Program<'info, Token>,
+    mint: &Pubkey,
+    bump: u8,
+    fee: u64,
+) -> Result<u64> {
+    let mut fee_share = 0;
+    if let (Some(fund), Some(insurance_vault)) = (insurance_fund, insurance_vault) {
+        fee_share = ((fee as u128) * (fund.fee_share_bps as u128) / 10000) as u64;
+        if fee_share > 0 {
+            let seeds = &[
+                b"vault".as_ref(),
+                mint.as_ref(),
+                &[bump],
+            ];
+            let signer = &[&seeds[..]];
+            let cpi_accounts = Transfer {
+                from: vault.to_account_info(),
+                to: insurance_vault.to_account_info(),
+                authority: vault.to_account_info(),
+            };
+            let cpi_ctx = CpiContext::new_with_signer(token_program.to_account_info(), cpi_accounts, signer);
+            token::transfer(cpi_ctx, fee_share)?;
+        }
+    }
+    Ok(fee_share)
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug, PartialEq, Eq)]
+pub struct FlashLoanLeg {
+    pub mint: Pubkey,
+    pub amount: u64,
+    pub fee: u64,
+}
+
+/// Arguments of `on_flash_loan_multi`, the receiver callback of
+/// `flash_loan_multi`. Accounts are passed as for `on_flash_loan`, and every
+/// leg's `amount + fee` must be returned to its vault.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug, PartialEq, Eq)]
+pub struct OnFlashLoanMulti {
+    pub loans: Vec<FlashLoanLeg>,
+    pub initiator: Pubkey,
+    pub data: Vec<u8>,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug)]
+pub struct FlashLoanRequest {
+    pub mint: Pubkey,
+    pub amount: u64,
+}
+
+#[derive(Accounts)]
+pub struct FlashLoanMulti<'info> {
+    #[account(mut)]
+    pub borrower: Signer<'info>,
+    pub token_program: Program<'info, Token>,
+    /// CHECK: The program that will be called via CPI
+    pub target_program: AccountInfo<'info>,
+    /// CHECK: PDA signing the callback, holds no data
+    #[account(seeds = [FLASH_AUTHORITY_SEED], bump)]
+    pub flash_authority: UncheckedAccount<'info>,
+    #[account(mut, seeds = [b"config"], bump = config.bump)]
+    pub config: Account<'info, ProtocolConfig>,
+}
+
+/// Flash loan of several assets with one callback. `remaining_accounts`
+/// starts with a `(vault, borrower token account, insurance fund, insurance
+/// vault)` group per entry of `loans`; the rest are passed to the receiver.
+/// The insurance accounts are the mint's PDAs and receive the fund's share
+/// of that leg's fee once the fund is initialized. Each vault must end up
+/// holding its loan's fee more than before. Every vault is locked for the
+/// callback, so at most `MAX_LOCKED_VAULTS` loans can be taken at once.
+pub fn flash_loan_multi<'info>(
+    ctx: Context<'_, '_, 'info, 'info, FlashLoanMulti<'info>>,
+    loans: Vec<FlashLoanRequest>,
+    data: Vec<u8>,
+) -> Result<()> {
+    if loans.is_empty() || loans.len() > MAX_LOCKED_VAULTS {
+        return Err(ErrorCode::InvalidParameter.into());
+    }
+    let loan_accounts_len = loans.len()
+        .checked_mul(MULTI_LEG_ACCOUNTS)
+        .ok_or(ErrorCode::MathOverflow)?;
+    if ctx.remaining_accounts.len() < loan_accounts_len {
+        return Err(anchor_lang::error::ErrorCode::AccountNotEnoughKeys.into());
+    }
+    let (loan_accounts, callback_accounts) = ctx.remaining_accounts.split_at(loan_accounts_len);
+    let token_program = ctx.accounts.token_program.to_account_info();
+
+    let mut legs = Vec::with_capacity(loans.len());
+    let mut vaults = Vec::with_capacity(loans.len());
+    let mut bumps = Vec::with_capacity(loans.len());
+    let mut balances_before = Vec::with_capacity(loans.len());
+    for (i, (loan, accounts)) in loans.iter().zip(loan_accounts.chunks(MULTI_LEG_ACCOUNTS)).enumerate() {
+        if loans[..i].iter().any(|other| other.mint == loan.mint) {
+            return Err(ErrorCode::InvalidParameter.into());
+        }
+        let (vault_key, bump) = Pubkey::find_program_address(&[b"vault", loan.mint.as_ref()], &crate::ID);
+        if accounts[0].key() != vault_key {
+            return Err(ErrorCode::InvalidVault.into());
+        }
+        let vault = Account::<TokenAccount>::try_from(&accounts[0])?;
+        lock_vault(&mut ctx.accounts.config, vault_key)?;
+
+        let fee = loan_fee(&ctx.accounts.config, &loan.mint, loan.amount, Some(&ctx.accounts.target_program.key()))?;
+        balances_before.push(vault.amount);
+        bumps.push(bump);
+        vaults.push(vault_key);
+        legs.push(FlashLoanLeg { mint: loan.mint, amount: loan.amount, fee });
+
+        liquidation::transfer_from_vault(&accounts[0], &accounts[1], &token_program, &loan.mint, bump, loan.amount)?;
+    }
+    ctx.accounts.config.exit(&crate::ID)?;
+
+    let args = OnFlashLoanMulti {
+        loans: legs,
+        initiator: ctx.accounts.borrower.key(),
+        data,
+    };
+    invoke_receiver(
+        &ctx.accounts.target_program,
+        &ctx.accounts.flash_authority,
+        ctx.bumps.flash_authority,
+        callback_accounts,
+        receiver_data(ON_FLASH_LOAN_MULTI_DISCRIMINATOR, &args)?,
+        &vaults,
+    )?;
+
+    ctx.accounts.config.reload()?;
+    ctx.accounts.config.locked_vaults.retain(|v| !vaults.contains(v));
+    let legs = args.loans.iter().zip(loan_accounts.chunks(MULTI_LEG_ACCOUNTS)).zip(balances_before.into_iter().zip(bumps));
+    for ((leg, accounts), (before, bump)) in legs {
+        let vault = Account::<TokenAccount>::try_from(&accounts[0])?;
+        if vault.amount < before.checked_add(leg.fee).ok_or(ErrorCode::MathOverflow)? {
+            return Err(ErrorCode::FlashReplyError.into());
+        }
+        let (insurance_fund, insurance_vault) = leg_insurance_accounts(&accounts[2], &accounts[3], &leg.mint)?;
+        let insured = route_insurance_fee(
+            &vault,
+            &insurance_fund,
+            &insurance_vault,
+            &ctx.accounts.token_program,
+            &leg.mint,
+            bump,
+            leg.fee,
+        )?;
+        book_flash_loan_fee(&mut ctx.accounts.config, &leg.mint, leg.fee - insured)?;
+    }
+
     Ok(())
 }
+
+/// Accounts per loan at the start of `flash_loan_multi`'s `remaining_accounts`.
+const MULTI_LEG_ACCOUNTS: usize = 4;
+
+/// An insurance fund and its vault, as optionally passed to `flash_loan`.
+type InsuranceAccounts<'info> = (Option<Account<'info, InsuranceFund>>, Option<Account<'info, TokenAccount>>);
+
+/// The insurance fund and vault of a `flash_loan_multi` leg, which must be
+/// the PDAs of `mint`. Both are `None` while the fund is uninitialized.
+fn leg_insurance_accounts<'info>(
+    fund: &'info AccountInfo<'info>,
+    vault: &'info AccountInfo<'info>,
+    mint: &Pubkey,
+) -> Result<InsuranceAccounts<'info>> {
+    let fund_key = Pubkey::find_program_address(&[b"insurance", mint.as_ref()], &crate::ID).0;
+    let vault_key = Pubkey::find_program_address(&[b"insurance_vault", mint.as_ref()], &crate::ID).0;
+    if fund.key() != fund_key || vault.key() != vault_key {
+        return Err(ErrorCode::InvalidVault.into());
+    }
+    if fund.owner != &crate::ID || fund.data_is_empty() {
+        return Ok((None, None));
+    }
+    Ok((Some(Account::try_from(fund)?), Some(Account::try_from(vault)?)))
+}
+
+#[derive(Accounts)]
+pub struct FlashMint<'info> {
+    #[account(mut)]
+    pub borrower: Signer<'info>,
+    #[account(mut, token::mint = borrow_mint)]
+    pub borrower_token_account: Account<'info, TokenAccount>,
+    /// Receives the fee, which is then accounted like a `flash_loan` fee.
+    #[account(
+        mut,
+        seeds = [b"vault", borrow_mint.key().as_ref()],
+        bump
+    )]
+    pub vault: Account<'info, TokenAccount>,
+    #[account(mut)]
+    pub borrow_mint: Account<'info, Mint>,
+    #[account(mut, seeds = [b"config"], bump = config.bump)]
+    pub config: Account<'info, ProtocolConfig>,
+    pub token_program: Program<'info, Token>,
+    /// CHECK: The program that will be called via CPI
+    pub target_program: AccountInfo<'info>,
+    /// CHECK: PDA signing the callback, holds no data
+    #[account(seeds = [FLASH_AUTHORITY_SEED], bump)]
+    pub flash_authority: UncheckedAccount<'info>,
+    /// Optional. When supplied with its vault, receives the fund's share of
+    /// the fee.
+    #[account(seeds = [b"insurance", borrow_mint.key().as_ref()], bump)]
+    pub insurance_fund: Option<Account<'info, InsuranceFund>>,
+    #[account(mut, seeds = [b"insurance_vault", borrow_mint.key().as_ref()], bump)]
+    pub insurance_vault: Option<Account<'info, TokenAccount>>,
+}
+
+/// Mints `amount` of a synthetic borrow asset to the borrower, calls the
+/// receiver's `on_flash_loan`, then burns `amount` from and charges the fee
+/// to `borrower_token_account`. Bounded by the asset's `flash_mint_ceiling`.
+/// The fee goes to the asset's vault, where the insurance fund's share is
+/// split off and the rest booked to reserves, as for `flash_loan`.
+pub fn flash_mint<'info>(
+    ctx: Context<'_, '_, 'info, 'info, FlashMint<'info>>,
+    amount: u64,
+    data: Vec<u8>,
+) -> Result<()> {
+    let mint_key = ctx.accounts.borrow_mint.key();
+    let asset = find_borrow_asset(&mut ctx.accounts.config, &mint_key)?;
+    if amount > asset.flash_mint_ceiling {
+        return Err(ErrorCode::FlashMintCeilingExceeded.into());
+    }
+    let fee = loan_fee(&ctx.accounts.config, &mint_key, amount, Some(&ctx.accounts.target_program.key()))?;
+    // The fee must not land in a vault whose own flash loan is being checked.
+    check_vault_unlocked(&ctx.accounts.config, &ctx.accounts.vault.key())?;
+
+    // The mint is locked like a vault so flash mints cannot nest past the
+    // ceiling.
+    lock_vault(&mut ctx.accounts.config, mint_key)?;
+    ctx.accounts.config.exit(&crate::ID)?;
+
+    let bump = ctx.accounts.config.bump;
+    let seeds = &[
+        b"config".as_ref(),
+        &[bump],
+    ];
+    let signer = &[&seeds[..]];
+    let cpi_accounts = MintTo {
+        mint: ctx.accounts.borrow_mint.to_account_info(),
+        to: ctx.accounts.borrower_token_account.to_account_info(),
+        authority: ctx.accounts.config.to_account_info(),
+    };
+    let cpi_ctx = CpiContext::new_with_signer(ctx.accounts.token_program.to_account_info(), cpi_accounts, signer);
+    token::mint_to(cpi_ctx, amount)?;
+
+    let args = OnFlashLoan {
+        mint: mint_key,
+        amount,
+        fee,
+        initiator: ctx.accounts.borrower.key(),
+        data,
+    };
+    invoke_receiver(
+        &ctx.accounts.target_program,
+        &ctx.accounts.flash_authority,
+        ctx.bumps.flash_authority,
+        ctx.remaining_accounts,
+        receiver_data(ON_FLASH_LOAN_DISCRIMINATOR, &args)?,
+        &[],
+    )?;
+
+    ctx.accounts.config.reload()?;
+    ctx.accounts.config.locked_vaults.retain(|v| *v != mint_key);
+    ctx.accounts.borrower_token_account.reload()?;
+    if ctx.accounts.borrower_token_account.amount < amount.checked_add(fee).ok_or(ErrorCode::MathOverflow)? {
+        return Err(ErrorCode::FlashReplyError.into());
+    }
+
+    let cpi_accounts = Burn {
+        mint: ctx.accounts.borrow_mint.to_account_info(),
+        from: ctx.accounts.borrower_token_account.to_account_info(),
+        authority: ctx.accounts.borrower.to_account_info(),
+    };
+    let cpi_ctx = CpiContext::new(ctx.accounts.token_program.to_account_info(), cpi_accounts);
+    token::burn(cpi_ctx, amount)?;
+
+    if fee > 0 {
+        let cpi_accounts = Transfer {
+            from: ctx.accounts.borrower_token_account.to_account_info(),
+            to: ctx.accounts.vault.to_account_info(),
+            authority: ctx.accounts.borrower.to_account_info(),
+        };
+        let cpi_ctx = CpiContext::new(ctx.accounts.token_program.to_account_info(), cpi_accounts);
+        token::transfer(cpi_ctx, fee)?;
+    }
+
+    let insured = route_insurance_fee(
+        &ctx.accounts.vault,
+        &ctx.accounts.insurance_fund,
+        &ctx.accounts.insurance_vault,
+        &ctx.accounts.token_program,
+        &mint_key,
+        ctx.bumps.vault,
+        fee,
+    )?;
+    book_flash_loan_fee(&mut ctx.accounts.config, &mint_key, fee - insured)
+}
+
+#[derive(Accounts)]
+pub struct FlashBorrow<'info> {
+    #[account(mut)]
+    pub borrower: Signer<'info>,
+    #[account(mut)]
+    pub borrower_token_account: Account<'info, TokenAccount>,
+    #[account(
+        mut,
+        seeds = [b"vault", borrow_mint.key().as_ref()],
+        bump
+    )]
+    pub vault: Account<'info, TokenAccount>,
+    pub borrow_mint: Account<'info, Mint>,
+    pub token_program: Program<'info, Token>,
+    /// CHECK: Address checked against the instructions sysvar
+    #[account(address = instructions_sysvar::ID)]
+    pub instructions: AccountInfo<'info>,
+    #[account(seeds = [b"config"], bump = config.bump)]
+    pub config: Account<'info, ProtocolConfig>,
+}
+
+/// Lends `amount` out of the vault for the rest of the transaction. Must be a
+/// top-level instruction followed, before any other `flash_borrow`, by a
+/// `flash_repay` on the same vault that points back at it.
+pub fn flash_borrow(ctx: Context<FlashBorrow>, amount: u64) -> Result<()> {
+    check_vault_unlocked(&ctx.accounts.config, &ctx.accounts.vault.key())?;
+    let instructions = &ctx.accounts.instructions;
+    let current_index = load_current_index_checked(instructions)? as usize;
+    let current = load_instruction_at_checked(current_index, instructions)?;
+    if current.program_id != crate::ID {
+        return Err(ErrorCode::InvalidFlashInstruction.into());
+    }
+
+    let mut index = current_index + 1;
+    loop {
+        let ix = match load_instruction_at_checked(index, instructions) {
+            Ok(ix) => ix,
+            Err(ProgramError::InvalidArgument) => return Err(ErrorCode::FlashRepayMissing.into()),
+            Err(err) => return Err(err.into()),
+        };
+        if ix.program_id == crate::ID {
+            let discriminator = ix.data.get(..8);
+            if discriminator == Some(crate::instruction::FlashBorrow::DISCRIMINATOR) {
+                return Err(ErrorCode::InvalidFlashInstruction.into());
+            }
+            if discriminator == Some(crate::instruction::FlashRepay::DISCRIMINATOR) {
+                let borrow_index = ix.data.get(8).map(|&i| i as usize);
+                let vault = ix.accounts.get(FLASH_VAULT_INDEX).map(|meta| meta.pubkey);
+                if borrow_index != Some(current_index) || vault != Some(ctx.accounts.vault.key()) {
+                    return Err(ErrorCode::InvalidFlashInstruction.into());
+                }
+                break;
+            }
+        }
+        index += 1;
+    }
+
+    let mint_key = ctx.accounts.borrow_mint.key();
+    let seeds = &[
+        b"vault".as_ref(),
+        mint_key.as_ref(),
+        &[ctx.bumps.vault],
+    ];
+    let signer = &[&seeds[..]];
+
+    let cpi_accounts = Transfer {
+        from: ctx.accounts.vault.to_account_info(),
+        to: ctx.accounts.borrower_token_account.to_account_info(),
+        authority: ctx.accounts.vault.to_account_info(),
+    };
+    let cpi_ctx = CpiContext::new_with_signer(ctx.accounts.token_program.to_account_info(), cpi_accounts, signer);
+    token::transfer(cpi_ctx, amount)
+}
+
+#[derive(Accounts)]
+pub struct FlashRepay<'info> {
+    #[account(mut)]
+    pub borrower: Signer<'info>,
+    #[account(mut)]
+    pub borrower_token_account: Account<'info, TokenAccount>,
+    #[account(
+        mut,
+        seeds = [b"vault", borrow_mint.key().as_ref()],
+        bump
+    )]
+    pub vault: Account<'info, TokenAccount>,
+    pub borrow_mint: Account<'info, Mint>,
+    pub token_program: Program<'info, Token>,
+    /// CHECK: Address checked against the instructions sysvar
+    #[account(address = instructions_sysvar::ID)]
+    pub instructions: AccountInfo<'info>,
+    #[account(mut, seeds = [b"config"], bump = config.bump)]
+    pub config: Account<'info, ProtocolConfig>,
+    #[account(seeds = [b"insurance", borrow_mint.key().as_ref()], bump)]
+    pub insurance_fund: Option<Account<'info, InsuranceFund>>,
+    #[account(mut, seeds = [b"insurance_vault", borrow_mint.key().as_ref()], bump)]
+    pub insurance_vault: Option<Account<'info, TokenAccount>>,
+}
+
+/// Repays the `flash_borrow` at `borrow_instruction_index` plus the flash
+/// loan fee.
+pub fn flash_repay(ctx: Context<FlashRepay>, borrow_instruction_index: u8) -> Result<()> {
+    check_vault_unlocked(&ctx.accounts.config, &ctx.accounts.vault.key())?;
+    let instructions = &ctx.accounts.instructions;
+    let current_index = load_current_index_checked(instructions)?;
+    if borrow_instruction_index as u16 >= current_index {
+        return Err(ErrorCode::InvalidFlashInstruction.into());
+    }
+    let borrow = load_instruction_at_checked(borrow_instruction_index as usize, instructions)?;
+    let vault = borrow.accounts.get(FLASH_VAULT_INDEX).map(|meta| meta.pubkey);
+    if borrow.program_id != crate::ID
+        || borrow.data.get(..8) != Some(crate::instruction::FlashBorrow::DISCRIMINATOR)
+        || vault != Some(ctx.accounts.vault.key())
+    {
+        return Err(ErrorCode::InvalidFlashInstruction.into());
+    }
+    let amount = borrow.data.get(8..16)
+        .and_then(|bytes| bytes.try_into().ok())
+        .map(u64::from_le_bytes)
+        .ok_or(ErrorCode::InvalidFlashInstruction)?;
+
+    let mint_key = ctx.accounts.borrow_mint.key();
+    let fee = loan_fee(&ctx.accounts.config, &mint_key, amount, None)?;
+    let cpi_accounts = Transfer {
+        from: ctx.accounts.borrower_token_account.to_account_info(),
+        to: ctx.accounts.vault.to_account_info(),
+        authority: ctx.accounts.borrower.to_account_info(),
+    };
+    let cpi_ctx = CpiContext::new(ctx.accounts.token_program.to_account_info(), cpi_accounts);
+    token::transfer(cpi_ctx, amount.checked_add(fee).ok_or(ErrorCode::MathOverflow)?)?;
+
+    let insured = route_insurance_fee(
+        &ctx.accounts.vault,
+        &ctx.accounts.insurance_fund,
+        &ctx.accounts.insurance_vault,
+        &ctx.accounts.token_program,
+        &mint_key,
+        ctx.bumps.vault,
+        fee,
+    )?;
+    book_flash_loan_fee(&mut ctx.accounts.config, &mint_key, fee - insured)
+}
+
+/// Invokes `target_program` with `accounts` passed through as-is, keeping the
+/// signer and writable flags they arrived with. The callback may not be this
+/// program, and may only see `vault` as a non-signer (it needs to be
+/// writable for repayment).
+pub fn invoke_callback<'info>(
+    target_program: &AccountInfo<'info>,
+    accounts: &[AccountInfo<'info>],
+    data: Vec<u8>,
+    vault: &Pubkey,
+) -> Result<()> {
+    let instruction = Instruction {
+        program_id: target_program.key(),
+        accounts: callback_metas(target_program, accounts, &[*vault])?,
+        data,
+    };
+
+    invoke(&instruction, accounts)?;
+    Ok(())
+}
+
+/// Encodes a receiver instruction the way Anchor would.
+pub fn receiver_data<T: AnchorSerialize>(discriminator: [u8; 8], args: &T) -> Result<Vec<u8>> {
+    let mut data = discriminator.to_vec();
+    args.serialize(&mut data)?;
+    Ok(data)
+}
+
+/// Calls a receiver instruction on `target_program`, signed by the flash
+/// authority PDA which is passed ahead of `accounts`.
+pub fn invoke_receiver<'info>(
+    target_program: &AccountInfo<'info>,
+    flash_authority: &AccountInfo<'info>,
+    flash_authority_bump: u8,
+    accounts: &[AccountInfo<'info>],
+    data: Vec<u8>,
+    vaults: &[Pubkey],
+) -> Result<()> {
+    let mut account_metas = vec![AccountMeta::new_readonly(flash_authority.key(), true)];
+    account_metas.extend(callback_metas(target_program, accounts, vaults)?);
+
+    let instruction = Instruction {
+        program_id: target_program.key(),
+        accounts: account_metas,
+        data,
+    };
+
+    let mut account_infos = Vec::with_capacity(accounts.len() + 1);
+    account_infos.push(flash_authority.clone());
+    account_infos.extend_from_slice(accounts);
+    invoke_signed(&instruction, &account_infos, &[&[FLASH_AUTHORITY_SEED, &[flash_authority_bump]]])?;
+    Ok(())
+}
+
+fn callback_metas(target_program: &AccountInfo, accounts: &[AccountInfo], vaults: &[Pubkey]) -> Result<Vec<AccountMeta>> {
+    if target_program.key() == crate::ID {
+        return Err(ErrorCode::Unauthorized.into());
+    }
+    if accounts.iter().any(|acc| vaults.contains(acc.key) && acc.is_signer) {
+        return Err(ErrorCode::InvalidCallbackAccounts.into());
+    }
+
+    let mut account_metas = Vec::new();
+    for acc in accounts.iter() {
+        account_metas.push(if acc.is_writable {
+            AccountMeta::new(acc.key(), acc.is_signer)
+        } else {
+            AccountMeta::new_readonly(acc.key(), acc.is_signer)
+        });
+    }
+    Ok(account_metas)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::CollateralInfo;
+    use crate::state::test_fixtures::{borrow_asset, collateral_info, protocol_config};
+
+    #[test]
+    fn test_flash_loan_fee() {
+        assert_eq!(flash_loan_fee(1_000_000, 5).unwrap(), 500);
+        assert_eq!(flash_loan_fee(u64::MAX, 10000).unwrap(), u64::MAX);
+        assert_eq!(flash_loan_fee(1_999, 5).unwrap(), 0);
+    }
+
+    #[test]
+    fn test_accrue_flash_loan_fee_books_reserves() {
+        let mut asset = BorrowAssetInfo { total_deposits: 1_000, ..borrow_asset() };
+        accrue_flash_loan_fee(&mut asset, 100).unwrap();
+        assert_eq!(asset.reserves, 100);
+        assert_eq!(asset.total_deposits, 1_000);
+    }
+
+    #[test]
+    fn test_flash_loan_from_collateral_vault() {
+        let mut config = protocol_config(vec![borrow_asset()]);
+        let collateral_mint = Pubkey::new_unique();
+        config.supported_collaterals.push(CollateralInfo {
+            flash_loan_fee_bps: 9,
+            ..collateral_info(collateral_mint, Pubkey::new_unique())
+        });
+        assert_eq!(loan_fee(&config, &collateral_mint, 1_000_000, None).unwrap(), 900);
+        book_flash_loan_fee(&mut config, &collateral_mint, 900).unwrap();
+        assert_eq!(config.supported_collaterals[0].reserves, 900);
+        assert_eq!(config.supported_borrows[0].reserves, 0);
+
+        let exempt = Pubkey::new_unique();
+        config.fee_exempt_programs.push(exempt);
+        assert_eq!(loan_fee(&config, &collateral_mint, 1_000_000, Some(&exempt)).unwrap(), 0);
+
+        let unlisted = Pubkey::new_unique();
+        assert_eq!(loan_fee(&config, &unlisted, 1_000_000, None).unwrap_err(), ErrorCode::InvalidVault.into());
+        assert!(book_flash_loan_fee(&mut config, &unlisted, 1).is_err());
+    }
+}
@@ -0,0 +1,396 @@
+use anchor_lang::prelude::*;
+use anchor_spl::token::{self, Mint, Token, TokenAccount, Transfer, Burn};
+use crate::state::*;
+use crate::errors::ErrorCode;
+
+#[derive(Accounts)]
+pub struct InitializeInsuranceFund<'info> {
+    #[account(
+        init,
+        payer = admin,
+        space = 8 + 32 + 8 + 8 + 8 + 8 + 8 + 8 + 1,
+        seeds = [b"insurance", mint.key().as_ref()],
+        bump
+    )]
+    pub insurance_fund: Account<'info, InsuranceFund>,
+    #[account(
+        init,
+        payer = admin,
+        token::mint = mint,
+        token::authority = insurance_vault,
+        seeds = [b"insurance_vault", mint.key().as_ref()],
+        bump
+    )]
+    pub insurance_vault: Account<'info, TokenAccount>,
+    pub mint: Account<'info, Mint>,
+    #[account(
+        seeds = [b"config"],
+        bump = config.bump,
+        has_one = admin
+    )]
+    pub config: Account<'info, ProtocolConfig>,
+    #[account(mut)]
+    pub admin: Signer<'info>,
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
+    pub rent: Sysvar<'info, Rent>,
+}
+
+pub fn initialize_insurance_fund(
+    ctx: Context<InitializeInsuranceFund>,
+    cooldown_seconds: u64,
+    unstake_window_seconds: u64,
+    max_slash_bps: u64,
+    fee_share_bps: u64,
+) -> Result<()> {
+    if max_slash_bps > 10000 || fee_share_bps > 10000 || unstake_window_seconds == 0 {
+        return Err(ErrorCode::InvalidParameter.into());
+    }
+
+    let fund = &mut ctx.accounts.insurance_fund;
+    fund.mint = ctx.accounts.mint.key();
+    fund.total_shares = 0;
+    fund.cooldown_seconds = cooldown_seconds;
+    fund.unstake_window_seconds = unstake_window_seconds;
+    fund.max_slash_bps = max_slash_bps;
+    fund.fee_share_bps = fee_share_bps;
+    fund.share_epoch = 0;
+    fund.bump = ctx.bumps.insurance_fund;
+    Ok(())
+}
+
+#[derive(Accounts)]
+pub struct OpenInsuranceStake<'info> {
+    #[account(
+        init,
+        payer = owner,
+        space = 8 + 32 + 32 + 8 + 8 + 8 + 1,
+        seeds = [b"insurance_stake", insurance_fund.mint.as_ref(), owner.key().as_ref()],
+        bump
+    )]
+    pub stake: Account<'info, InsuranceStake>,
+    #[account(seeds = [b"insurance", insurance_fund.mint.as_ref()], bump = insurance_fund.bump)]
+    pub insurance_fund: Account<'info, InsuranceFund>,
+    #[account(mut)]
+    pub owner: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+pub fn open_insurance_stake(ctx: Context<OpenInsuranceStake>) -> Result<()> {
+    let stake = &mut ctx.accounts.stake;
+    stake.owner = ctx.accounts.owner.key();
+    stake.mint = ctx.accounts.insurance_fund.mint;
+    stake.shares = 0;
+    stake.cooldown_start_ts = 0;
+    stake.share_epoch = ctx.accounts.insurance_fund.share_epoch;
+    stake.bump = ctx.bumps.stake;
+    Ok(())
+}
+
+#[derive(Accounts)]
+pub struct StakeInsurance<'info> {
+    #[account(
+        mut,
+        seeds = [b"insurance_stake", insurance_fund.mint.as_ref(), owner.key().as_ref()],
+        bump = stake.bump
+    )]
+    pub stake: Account<'info, InsuranceStake>,
+    #[account(mut, seeds = [b"insurance", insurance_fund.mint.as_ref()], bump = insurance_fund.bump)]
+    pub insurance_fund: Account<'info, InsuranceFund>,
+    #[account(
+        mut,
+        seeds = [b"insurance_vault", insurance_fund.mint.as_ref()],
+        bump
+    )]
+    pub insurance_vault: Account<'info, TokenAccount>,
+    #[account(mut)]
+    pub owner: Signer<'info>,
+    #[account(mut)]
+    pub user_token_account: Account<'info, TokenAccount>,
+    pub token_program: Program<'info, Token>,
+}
+
+pub fn stake_insurance(ctx: Context<StakeInsurance>, amount: u64) -> Result<()> {
+    let fund = &mut ctx.accounts.insurance_fund;
+    if fund.total_shares > 0 && ctx.accounts.insurance_vault.amount == 0 {
+        // A slash took everything: the outstanding shares are worthless and
+        // must not dilute new stakers.
+        fund.total_shares = 0;
+        fund.share_epoch = fund.share_epoch.checked_add(1).ok_or(ErrorCode::MathOverflow)?;
+    }
+    sync_share_epoch(&mut ctx.accounts.stake, fund);
+    let shares = shares_for_deposit(amount, fund.total_shares, ctx.accounts.insurance_vault.amount)?;
+
+    let cpi_accounts = Transfer {
+        from: ctx.accounts.user_token_account.to_account_info(),
+        to: ctx.accounts.insurance_vault.to_account_info(),
+        authority: ctx.accounts.owner.to_account_info(),
+    };
+    let cpi_program = ctx.accounts.token_program.to_account_info();
+    let cpi_ctx = CpiContext::new(cpi_program, cpi_accounts);
+    token::transfer(cpi_ctx, amount)?;
+
+    fund.total_shares = fund.total_shares.checked_add(shares).ok_or(ErrorCode::MathOverflow)?;
+    let stake = &mut ctx.accounts.stake;
+    stake.shares = stake.shares.checked_add(shares).ok_or(ErrorCode::MathOverflow)?;
+    // New funds must sit through a cooldown of their own.
+    stake.cooldown_start_ts = 0;
+    Ok(())
+}
+
+#[derive(Accounts)]
+pub struct RequestInsuranceUnstake<'info> {
+    #[account(
+        mut,
+        seeds = [b"insurance_stake", stake.mint.as_ref(), owner.key().as_ref()],
+        bump = stake.bump,
+        has_one = owner
+    )]
+    pub stake: Account<'info, InsuranceStake>,
+    pub owner: Signer<'info>,
+}
+
+pub fn request_insurance_unstake(ctx: Context<RequestInsuranceUnstake>) -> Result<()> {
+    ctx.accounts.stake.cooldown_start_ts = Clock::get()?.unix_timestamp;
+    Ok(())
+}
+
+#[derive(Accounts)]
+pub struct UnstakeInsurance<'info> {
+    #[account(
+        mut,
+        seeds = [b"insurance_stake", insurance_fund.mint.as_ref(), owner.key().as_ref()],
+        bump = stake.bump
+    )]
+    pub stake: Account<'info, InsuranceStake>,
+    #[account(mut, seeds = [b"insurance", insurance_fund.mint.as_ref()], bump = insurance_fund.bump)]
+    pub insurance_fund: Account<'info, InsuranceFund>,
+    #[account(
+        mut,
+        seeds = [b"insurance_vault", insurance_fund.mint.as_ref()],
+        bump
+    )]
+    pub insurance_vault: Account<'info, TokenAccount>,
+    #[account(mut)]
+    pub owner: Signer<'info>,
+    #[account(mut)]
+    pub user_token_account: Account<'info, TokenAccount>,
+    pub token_program: Program<'info, Token>,
+}
+
+pub fn unstake_insurance(ctx: Context<UnstakeInsurance>, shares: u64) -> Result<()> {
+    let fund = &mut ctx.accounts.insurance_fund;
+    let stake = &mut ctx.accounts.stake;
+    let now = Clock::get()?.unix_timestamp;
+    sync_share_epoch(stake, fund);
+
+    check_unstake_window(fund, stake.cooldown_start_ts, now)?;
+    if shares == 0 {
+        return Err(ErrorCode::InvalidParameter.into());
+    }
+    if shares > stake.shares {
+        return Err(ErrorCode::InsufficientShares.into());
+    }
+
+    let amount = amount_for_shares(shares, fund.total_shares, ctx.accounts.insurance_vault.amount)?;
+
+    stake.shares -= shares;
+    stake.cooldown_start_ts = 0;
+    fund.total_shares = fund.total_shares.checked_sub(shares).ok_or(ErrorCode::MathOverflow)?;
+
+    let mint_key = fund.mint;
+    let bump = ctx.bumps.insurance_vault;
+    let seeds = &[
+        b"insurance_vault".as_ref(),
+        mint_key.as_ref(),
+        &[bump],
+    ];
+    let signer = &[&seeds[..]];
+
+    let cpi_accounts = Transfer {
+        from: ctx.accounts.insurance_vault.to_account_info(),
+        to: ctx.accounts.user_token_account.to_account_info(),
+        authority: ctx.accounts.insurance_vault.to_account_info(),
+    };
+    let cpi_program = ctx.accounts.token_program.to_account_info();
+    let cpi_ctx = CpiContext::new_with_signer(cpi_program, cpi_accounts, signer);
+    token::transfer(cpi_ctx, amount)?;
+
+    Ok(())
+}
+
+/// Checks `now` falls in the unstake window, which opens once the cooldown
+/// started at `cooldown_start_ts` has elapsed and lasts
+/// `unstake_window_seconds`. Bounding it stops a staker from keeping an
+/// elapsed request around to exit the moment a write-off is coming.
+pub fn check_unstake_window(fund: &InsuranceFund, cooldown_start_ts: i64, now: i64) -> Result<()> {
+    if cooldown_start_ts == 0 {
+        return Err(ErrorCode::CooldownNotElapsed.into());
+    }
+    let window_start = cooldown_start_ts
+        .checked_add(fund.cooldown_seconds as i64)
+        .ok_or(ErrorCode::MathOverflow)?;
+    let window_end = window_start
+        .checked_add(fund.unstake_window_seconds as i64)
+        .ok_or(ErrorCode::MathOverflow)?;
+    if now < window_start {
+        return Err(ErrorCode::CooldownNotElapsed.into());
+    }
+    if now > window_end {
+        return Err(ErrorCode::UnstakeWindowExpired.into());
+    }
+    Ok(())
+}
+
+/// Virtual share and balance added to both sides of the exchange rate. A
+/// donation to the vault is then partly captured by the virtual share, so
+/// inflating the share price to round later stakes down costs more than it
+/// can take from them.
+const VIRTUAL_SHARES: u128 = 1;
+const VIRTUAL_BALANCE: u128 = 1;
+
+/// Drops the shares of a stake minted before the fund was emptied.
+fn sync_share_epoch(stake: &mut InsuranceStake, fund: &InsuranceFund) {
+    if stake.share_epoch != fund.share_epoch {
+        stake.shares = 0;
+        stake.cooldown_start_ts = 0;
+        stake.share_epoch = fund.share_epoch;
+    }
+}
+
+/// Shares minted for depositing `amount` into a fund holding `vault_balance`.
+/// The first staker gets one share per token, along with anything already in
+/// the vault. Stakes that would mint no shares are rejected.
+pub fn shares_for_deposit(amount: u64, total_shares: u64, vault_balance: u64) -> Result<u64> {
+    let shares = if total_shares == 0 {
+        amount as u128
+    } else {
+        (amount as u128)
+            .checked_mul(total_shares as u128 + VIRTUAL_SHARES)
+            .ok_or(ErrorCode::MathOverflow)?
+            / (vault_balance as u128 + VIRTUAL_BALANCE)
+    };
+    if shares == 0 {
+        return Err(ErrorCode::ZeroShares.into());
+    }
+    u64::try_from(shares).map_err(|_| ErrorCode::MathOverflow.into())
+}
+
+/// Tokens paid out for redeeming `shares` of a fund holding `vault_balance`.
+pub fn amount_for_shares(shares: u64, total_shares: u64, vault_balance: u64) -> Result<u64> {
+    let amount = (shares as u128)
+        .checked_mul(vault_balance as u128 + VIRTUAL_BALANCE)
+        .ok_or(ErrorCode::MathOverflow)?
+        .checked_div(total_shares as u128 + VIRTUAL_SHARES)
+        .ok_or(ErrorCode::MathOverflow)?;
+    u64::try_from(amount).map_err(|_| ErrorCode::MathOverflow.into())
+}
+
+/// Most a single write-off may take from a fund holding `vault_balance`.
+pub fn max_slashable(fund: &InsuranceFund, vault_balance: u64) -> u64 {
+    ((vault_balance as u128) * (fund.max_slash_bps as u128) / 10000) as u64
+}
+
+/// Burns `amount` from the insurance vault. Burning retires the synthetic
+/// supply that the written-off debt left unbacked.
+pub fn slash_insurance<'info>(
+    insurance_vault: &AccountInfo<'info>,
+    mint: &AccountInfo<'info>,
+    token_program: &AccountInfo<'info>,
+    amount: u64,
+) -> Result<()> {
+    let mint_key = mint.key();
+    let (_, bump) = Pubkey::find_program_address(&[b"insurance_vault", mint_key.as_ref()], &crate::ID);
+    let seeds = &[
+        b"insurance_vault".as_ref(),
+        mint_key.as_ref(),
+        &[bump],
+    ];
+    let signer = &[&seeds[..]];
+
+    let cpi_accounts = Burn {
+        mint: mint.clone(),
+        from: insurance_vault.clone(),
+        authority: insurance_vault.clone(),
+    };
+    let cpi_ctx = CpiContext::new_with_signer(token_program.clone(), cpi_accounts, signer);
+    token::burn(cpi_ctx, amount)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_shares_for_deposit() {
+        assert_eq!(shares_for_deposit(100, 0, 0).unwrap(), 100);
+        assert_eq!(shares_for_deposit(100, 0, 50).unwrap(), 100);
+        assert_eq!(shares_for_deposit(100, 999, 1_999).unwrap(), 50);
+        assert_eq!(shares_for_deposit(100, 999, 499).unwrap(), 200);
+        assert_eq!(amount_for_shares(50, 1_049, 2_099).unwrap(), 100);
+        assert_eq!(amount_for_shares(0, 0, 0).unwrap(), 0);
+    }
+
+    #[test]
+    fn test_shares_for_deposit_rejects_inflated_price() {
+        // One share backed by a donated 1_000_000: a stake that would round
+        // down to nothing is rejected rather than gifted to the fund.
+        assert_eq!(
+            shares_for_deposit(400_000, 1, 1_000_001).unwrap_err(),
+            ErrorCode::ZeroShares.into()
+        );
+        // A stake that does mint a share leaves part of the donation with the
+        // virtual share, so the donor gets back less than they put in.
+        let shares = shares_for_deposit(1_000_000, 1, 1_000_001).unwrap();
+        assert_eq!(shares, 1);
+        assert!(amount_for_shares(1, 2, 2_000_001).unwrap() < 1_000_001);
+    }
+
+    #[test]
+    fn test_emptied_fund_starts_a_new_epoch() {
+        let fund = InsuranceFund {
+            mint: Pubkey::default(),
+            total_shares: 0,
+            cooldown_seconds: 0,
+            unstake_window_seconds: 1,
+            max_slash_bps: 0,
+            fee_share_bps: 0,
+            share_epoch: 1,
+            bump: 0,
+        };
+        let mut stake = InsuranceStake {
+            owner: Pubkey::default(),
+            mint: Pubkey::default(),
+            shares: 500,
+            cooldown_start_ts: 10,
+            share_epoch: 0,
+            bump: 0,
+        };
+        sync_share_epoch(&mut stake, &fund);
+        assert_eq!(stake.shares, 0);
+        assert_eq!(stake.share_epoch, 1);
+        assert_eq!(shares_for_deposit(100, fund.total_shares, 0).unwrap(), 100);
+    }
+
+    #[test]
+    fn test_unstake_window() {
+        let fund = InsuranceFund {
+            mint: Pubkey::default(),
+            total_shares: 0,
+            cooldown_seconds: 100,
+            unstake_window_seconds: 50,
+            max_slash_bps: 0,
+            fee_share_bps: 0,
+            share_epoch: 0,
+            bump: 0,
+        };
+        assert!(check_unstake_window(&fund, 0, 1_000).is_err());
+        assert!(check_unstake_window(&fund, 1_000, 1_099).is_err());
+        assert!(check_unstake_window(&fund, 1_000, 1_100).is_ok());
+        assert!(check_unstake_window(&fund, 1_000, 1_150).is_ok());
+        assert_eq!(
+            check_unstake_window(&fund, 1_000, 1_151).unwrap_err(),
+            ErrorCode::UnstakeWindowExpired.into()
+        );
+    }
+}
@@ -0,0 +1,199 @@
+use anchor_lang::prelude::*;
+use anchor_spl::token::{self, Mint, MintTo, Token, TokenAccount, Transfer};
+use crate::state::*;
+use crate::errors::ErrorCode;
+use crate::math::*;
+use crate::points;
+use crate::health;
+use crate::instructions::{flashloan, swap, user};
+
+/// Leverage of 1x; `target_leverage` is expressed in these units.
+pub const LEVERAGE_SCALE: u64 = 10000;
+
+#[derive(Accounts)]
+pub struct OpenLeveragedPosition<'info> {
+    #[account(mut, seeds = [b"debt", owner.key().as_ref()], bump = debt_account.bump)]
+    pub debt_account: Account<'info, UserDebtAccount>,
+    #[account(mut)]
+    pub owner: Signer<'info>,
+    #[account(
+        mut,
+        token::mint = collateral_mint,
+        token::authority = owner
+    )]
+    pub user_collateral: Account<'info, TokenAccount>,
+    #[account(
+        mut,
+        token::mint = borrow_mint,
+        token::authority = owner
+    )]
+    pub user_borrow_account: Account<'info, TokenAccount>,
+    #[account(
+        mut,
+        seeds = [b"vault", collateral_mint.key().as_ref()],
+        bump
+    )]
+    pub vault: Account<'info, TokenAccount>,
+    pub collateral_mint: Account<'info, Mint>,
+    #[account(mut)]
+    pub borrow_mint: Account<'info, Mint>,
+    #[account(mut, seeds = [b"config"], bump = config.bump)]
+    pub config: Account<'info, ProtocolConfig>,
+    pub token_program: Program<'info, Token>,
+    /// CHECK: Verified in instruction
+    pub collateral_price_feed: AccountInfo<'info>,
+    /// CHECK: Verified in instruction
+    pub borrow_price_feed: AccountInfo<'info>,
+    /// CHECK: The venue's program, checked against `config.swap_programs`
+    pub swap_program: AccountInfo<'info>,
+}
+
+/// Loops `initial_amount` of collateral up to `target_leverage` in one
+/// instruction. The debt asset needed for the extra collateral is minted up
+/// front, which stands in for the flash loan, swapped into collateral
+/// through `route`, and deposited along with `initial_amount`; the mint is
+/// then booked as a borrow. Health is only checked once, on the finished
+/// position. The first `feed_count` remaining accounts are price feeds of the
+/// account's other positions; the rest are the venue's accounts.
+pub fn open_leveraged_position<'info>(
+    ctx: Context<'_, '_, 'info, 'info, OpenLeveragedPosition<'info>>,
+    initial_amount: u64,
+    target_leverage: u64,
+    min_out: u64,
+    route: swap::SwapRoute,
+    feed_count: u8,
+) -> Result<()> {
+    let config = &ctx.accounts.config;
+    let now = Clock::get()?.unix_timestamp;
+    let collateral_mint_key = ctx.accounts.collateral_mint.key();
+    let borrow_mint_key = ctx.accounts.borrow_mint.key();
+
+    if target_leverage < LEVERAGE_SCALE {
+        return Err(ErrorCode::InvalidParameter.into());
+    }
+    flashloan::check_vault_unlocked(config, &ctx.accounts.vault.key())?;
+    let collateral_info = config.supported_collaterals.iter().find(|c| c.mint == collateral_mint_key).ok_or(ErrorCode::UnsupportedCollateral)?;
+    if collateral_info.price_feed != ctx.accounts.collateral_price_feed.key() {
+        return Err(ErrorCode::InvalidPriceFeed.into());
+    }
+    let borrow_info = config.supported_borrows.iter().find(|b| b.mint == borrow_mint_key).ok_or(ErrorCode::UnsupportedBorrowAsset)?;
+    if borrow_info.price_feed != ctx.accounts.borrow_price_feed.key() {
+        return Err(ErrorCode::InvalidPriceFeed.into());
+    }
+
+    let feed_count = feed_count as usize;
+    if ctx.remaining_accounts.len() < feed_count {
+        return Err(anchor_lang::error::ErrorCode::AccountNotEnoughKeys.into());
+    }
+    let (extra_feeds, venue_accounts) = ctx.remaining_accounts.split_at(feed_count);
+
+    // Debt needed to buy the collateral that takes the position from 1x to
+    // `target_leverage`.
+    let collateral_price = get_price_from_feed(&ctx.accounts.collateral_price_feed, health::PRICE_MAX_AGE, now)?;
+    let borrow_price = get_price_from_feed(&ctx.accounts.borrow_price_feed, health::PRICE_MAX_AGE, now)?;
+    let extra_value = (initial_amount as u128)
+        .checked_mul((target_leverage - LEVERAGE_SCALE) as u128)
+        .ok_or(ErrorCode::MathOverflow)?
+        / (LEVERAGE_SCALE as u128)
+        * (collateral_price as u128);
+    let borrow_amount = u64::try_from(extra_value / (borrow_price as u128)).map_err(|_| ErrorCode::MathOverflow)?;
+
+    let mut swapped = 0;
+    if borrow_amount > 0 {
+        let result = mint_and_swap(
+            ctx.accounts,
+            venue_accounts,
+            &route,
+            borrow_amount,
+            min_out,
+            mint_borrow,
+            anchor_lang::solana_program::program::invoke,
+        )?;
+        swapped = result.amount_out;
+
+        // Nothing below should act on state the venue might have seen.
+        ctx.accounts.config.reload()?;
+        ctx.accounts.debt_account.reload()?;
+    }
+
+    let deposit_amount = initial_amount.checked_add(swapped).ok_or(ErrorCode::MathOverflow)?;
+    let cpi_accounts = Transfer {
+        from: ctx.accounts.user_collateral.to_account_info(),
+        to: ctx.accounts.vault.to_account_info(),
+        authority: ctx.accounts.owner.to_account_info(),
+    };
+    let cpi_ctx = CpiContext::new(ctx.accounts.token_program.to_account_info(), cpi_accounts);
+    token::transfer(cpi_ctx, deposit_amount)?;
+
+    let config = &mut ctx.accounts.config;
+    let debt_account = &mut ctx.accounts.debt_account;
+    points::update_points(debt_account, now)?;
+    user::record_deposit(debt_account, collateral_mint_key, deposit_amount)?;
+    if borrow_amount > 0 {
+        let asset = config.supported_borrows.iter_mut()
+            .find(|b| b.mint == borrow_mint_key)
+            .ok_or(ErrorCode::UnsupportedBorrowAsset)?;
+        user::record_borrow(asset, debt_account, borrow_amount, now as u64)?;
+    }
+
+    let mut feeds = vec![
+        ctx.accounts.collateral_price_feed.clone(),
+        ctx.accounts.borrow_price_feed.clone(),
+    ];
+    feeds.extend_from_slice(extra_feeds);
+    let report = health::compute_health(config, debt_account, &feeds, now)?;
+    if !report.is_within_borrow_limit() {
+        return Err(ErrorCode::LTVExceeded.into());
+    }
+    health::store_cached_health(debt_account, report, Clock::get()?.slot);
+    health::update_unhealthy_since(debt_account, &report, now);
+
+    Ok(())
+}
+
+/// Mints `borrow_amount` into the owner's borrow account with `mint` and
+/// swaps exactly that into collateral through `route`, the venue CPI made
+/// through `invoke_swap`. The swap's limits are measured from the balance
+/// after the mint, so the venue cannot also spend what the owner already
+/// held.
+pub fn mint_and_swap<'info>(
+    accounts: &mut OpenLeveragedPosition<'info>,
+    venue_accounts: &[AccountInfo<'info>],
+    route: &swap::SwapRoute,
+    borrow_amount: u64,
+    min_out: u64,
+    mint: impl FnOnce(&OpenLeveragedPosition<'info>, u64) -> Result<()>,
+    invoke_swap: swap::SwapInvoke<'info>,
+) -> Result<swap::SwapResult> {
+    mint(accounts, borrow_amount)?;
+    // `mint` changes the account's data, not this deserialized copy.
+    accounts.user_borrow_account.reload()?;
+
+    swap::swap_with_limits(
+        &accounts.config,
+        &accounts.swap_program,
+        venue_accounts,
+        route.adapter().instruction_data(borrow_amount, min_out)?,
+        &mut accounts.user_borrow_account,
+        &mut accounts.user_collateral,
+        min_out,
+        borrow_amount,
+        invoke_swap,
+    )
+}
+
+/// Mints `amount` of the debt asset to the owner, signed by the config PDA.
+fn mint_borrow(accounts: &OpenLeveragedPosition, amount: u64) -> Result<()> {
+    let seeds = &[
+        b"config".as_ref(),
+        &[accounts.config.bump],
+    ];
+    let signer = &[&seeds[..]];
+    let cpi_accounts = MintTo {
+        mint: accounts.borrow_mint.to_account_info(),
+        to: accounts.user_borrow_account.to_account_info(),
+        authority: accounts.config.to_account_info(),
+    };
+    let cpi_ctx = CpiContext::new_with_signer(accounts.token_program.to_account_info(), cpi_accounts, signer);
+    token::mint_to(cpi_ctx, amount)
+}
@@ -3,8 +3,420 @@ use anchor_spl::token::{self, Mint, Token, TokenAccount, Transfer, Burn};
 use crate::state::*;
 use crate::errors::ErrorCode;
 use crate::math::*;
+use crate::health::{self, HealthReport};
+use crate::instructions::{flashloan, insurance};
 
-pub const MAX_LIQUIDATION_CLOSE_FACTOR: u64 = 5000;
+pub const DEFAULT_CLOSE_FACTOR: u64 = 5000;
+/// Health factor below which a debt may be repaid in full by default: 0.9,
+/// above the point where partial liquidations start to lower health for
+/// typical thresholds and bonuses.
+pub const DEFAULT_FULL_LIQUIDATION_HEALTH_FACTOR: u64 = (health::HEALTH_FACTOR_SCALE * 9 / 10) as u64;
+/// Precision of each collateral's share of the seized value.
+pub const SHARE_PRECISION: u128 = 1_000_000_000;
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Seizure {
+    pub mint: Pubkey,
+    /// Taken from the account, protocol fee included.
+    pub amount: u64,
+    /// Part of `amount` paid to the treasury rather than the liquidator.
+    pub protocol_fee: u64,
+}
+
+/// Outcome of a liquidation computed without touching any state, so that
+/// every liquidation entry point applies exactly the same numbers.
+#[derive(Clone, Debug)]
+pub struct LiquidationPlan {
+    pub borrow_mint: Pubkey,
+    /// Debt owed in `borrow_mint` before the liquidation, interest included.
+    pub owed: u64,
+    pub repay_amount: u64,
+    /// One entry per requested collateral, in request order.
+    pub seizures: Vec<Seizure>,
+    /// Value seized above the repaid value, in `PRICE_PRECISION` units.
+    pub bonus_value: u128,
+    pub health_before: HealthReport,
+    pub health_after: HealthReport,
+}
+
+/// Bonus paid on a collateral whose configured bonus is `max_bonus`, for an
+/// account liquidatable since `unhealthy_since_ts`. Ramps linearly from the
+/// configured minimum to `max_bonus`; an account nobody has marked yet gets
+/// the minimum.
+pub fn effective_liquidation_bonus(
+    params: &LiquidationParams,
+    max_bonus: u64,
+    unhealthy_since_ts: i64,
+    now: i64,
+) -> u64 {
+    if params.bonus_ramp_duration == 0 {
+        return max_bonus;
+    }
+    let min_bonus = params.min_liquidation_bonus.min(max_bonus);
+    if unhealthy_since_ts == 0 {
+        return min_bonus;
+    }
+    let elapsed = now.saturating_sub(unhealthy_since_ts).max(0) as u64;
+    if elapsed >= params.bonus_ramp_duration {
+        return max_bonus;
+    }
+    min_bonus + ((max_bonus - min_bonus) as u128 * elapsed as u128 / params.bonus_ramp_duration as u128) as u64
+}
+
+/// Share of a debt worth `debt_value`, in basis points, that may be repaid
+/// on an account at `health_factor`. Deeply underwater accounts and dust
+/// debts may be closed in full.
+pub fn effective_close_factor(params: &LiquidationParams, health_factor: u128, debt_value: u128) -> u64 {
+    if health_factor < params.full_liquidation_health_factor as u128 || debt_value <= params.dust_debt_value {
+        10000
+    } else {
+        params.close_factor
+    }
+}
+
+/// A collateral the repayment is seized from.
+#[derive(Clone, Copy, Debug)]
+pub struct SeizureLeg {
+    pub price: u64,
+    pub liquidation_bonus: u64,
+    /// Value of the account's whole balance in this collateral.
+    pub value: u128,
+    /// Most that may be seized: the balance, or less if the liquidator capped it.
+    pub cap: u64,
+}
+
+/// Splits the value of `repay_amount`, plus each collateral's liquidation
+/// bonus, across `legs` in proportion to their value. If any leg would exceed
+/// its cap the repay amount is recomputed backwards from the tightest cap,
+/// rounding in the protocol's favour. Returns `(repay_amount, seized amounts)`.
+pub fn calculate_seizures(
+    repay_amount: u64,
+    borrow_price: u64,
+    legs: &[SeizureLeg],
+) -> Result<(u64, Vec<u64>)> {
+    let total_value = legs.iter()
+        .try_fold(0u128, |acc, leg| acc.checked_add(leg.value))
+        .ok_or(ErrorCode::MathOverflow)?;
+    if total_value == 0 {
+        return Err(ErrorCode::InsufficientCollateral.into());
+    }
+
+    let mut shares = Vec::with_capacity(legs.len());
+    for leg in legs {
+        let share = leg.value
+            .checked_mul(SHARE_PRECISION)
+            .ok_or(ErrorCode::MathOverflow)?
+            / total_value;
+        shares.push(share);
+    }
+
+    let mut repay = repay_amount as u128;
+    for (leg, &share) in legs.iter().zip(&shares) {
+        if share == 0 {
+            continue;
+        }
+        let max_seize_value = (leg.cap as u128)
+            .checked_mul(leg.price as u128)
+            .ok_or(ErrorCode::MathOverflow)?;
+        let max_repay_value = max_seize_value
+            .checked_mul(10000)
+            .ok_or(ErrorCode::MathOverflow)?
+            / (10000 + leg.liquidation_bonus as u128)
+            * SHARE_PRECISION
+            / share;
+        repay = repay.min(max_repay_value.div_ceil(borrow_price as u128));
+    }
+
+    let repay_value = repay
+        .checked_mul(borrow_price as u128)
+        .ok_or(ErrorCode::MathOverflow)?;
+    let mut amounts = Vec::with_capacity(legs.len());
+    for (leg, &share) in legs.iter().zip(&shares) {
+        let seize_value = repay_value
+            .checked_mul(share)
+            .ok_or(ErrorCode::MathOverflow)?
+            / SHARE_PRECISION
+            * (10000 + leg.liquidation_bonus as u128)
+            / 10000;
+        let amount = (seize_value / leg.price as u128).min(leg.cap as u128);
+        amounts.push(amount as u64);
+    }
+
+    Ok((repay as u64, amounts))
+}
+
+/// Treasury cut of the bonus contained in a seizure of `amount` paid at
+/// `liquidation_bonus`.
+pub fn protocol_fee(amount: u64, liquidation_bonus: u64, protocol_fee_bps: u64) -> u64 {
+    let at_par = (amount as u128) * 10000 / (10000 + liquidation_bonus as u128);
+    ((amount as u128 - at_par) * (protocol_fee_bps as u128) / 10000) as u64
+}
+
+/// Computes how much of `borrow_mint` debt a liquidator repays and what they
+/// seize from each of `collaterals`. `feeds` must contain the price feed of
+/// every position; interest is accrued up to `now` without being written.
+pub fn plan_liquidation(
+    config: &ProtocolConfig,
+    debt_account: &UserDebtAccount,
+    borrow_mint: Pubkey,
+    collaterals: &[CollateralToSeize],
+    amount: u64,
+    feeds: &[AccountInfo],
+    now: i64,
+) -> Result<LiquidationPlan> {
+    let borrow_info = config.supported_borrows.iter()
+        .find(|x| x.mint == borrow_mint)
+        .ok_or(ErrorCode::UnsupportedBorrowAsset)?;
+
+    let health_before = health::compute_health(config, debt_account, feeds, now)?;
+    if !health_before.is_liquidatable() {
+        return Err(ErrorCode::AccountHealthy.into());
+    }
+
+    let slot = debt_account.debt_balances.iter()
+        .find(|d| d.borrow_mint == borrow_mint)
+        .ok_or(ErrorCode::NoDebtToRepay)?;
+    let index = accrued_global_index(borrow_info, now as u64)?;
+    let owed = calculate_owed_amount(slot.principal, slot.interest_index_snapshot, index)?;
+
+    let borrow_price = get_price_from_feed(
+        health::find_price_feed(feeds, &borrow_info.price_feed)?,
+        health::PRICE_MAX_AGE,
+        now,
+    )?;
+
+    let debt_value = (owed as u128)
+        .checked_mul(borrow_price as u128)
+        .ok_or(ErrorCode::MathOverflow)?;
+    let close_factor = effective_close_factor(&config.liquidation_params, health_before.health_factor, debt_value);
+    let inputs = PlanInputs { borrow_mint, owed, index, borrow_price, health_before };
+    match plan_with_close_factor(config, debt_account, &inputs, collaterals, amount, close_factor, feeds, now) {
+        // Below roughly `(1 + bonus) * liquidation_threshold` any partial
+        // repay lowers the health factor; only repaying the debt in full or
+        // emptying the seized collateral is allowed then.
+        Err(err) if close_factor < 10000 && err == ErrorCode::LiquidationWorsensHealth.into() => {
+            plan_with_close_factor(config, debt_account, &inputs, collaterals, amount, 10000, feeds, now)
+        }
+        result => result,
+    }
+}
+
+/// Account state `plan_liquidation` prices every attempt against.
+struct PlanInputs {
+    borrow_mint: Pubkey,
+    owed: u64,
+    index: u128,
+    borrow_price: u64,
+    health_before: HealthReport,
+}
+
+#[allow(clippy::too_many_arguments)]
+fn plan_with_close_factor(
+    config: &ProtocolConfig,
+    debt_account: &UserDebtAccount,
+    inputs: &PlanInputs,
+    collaterals: &[CollateralToSeize],
+    amount: u64,
+    close_factor: u64,
+    feeds: &[AccountInfo],
+    now: i64,
+) -> Result<LiquidationPlan> {
+    let PlanInputs { borrow_mint, owed, index, borrow_price, health_before } = *inputs;
+    let max_liquidatable = ((owed as u128) * (close_factor as u128) / 10000) as u64;
+    let requested = amount.min(max_liquidatable);
+
+    let mut legs = Vec::with_capacity(collaterals.len());
+    for (i, c) in collaterals.iter().enumerate() {
+        if collaterals[..i].iter().any(|other| other.mint == c.mint) {
+            return Err(ErrorCode::InvalidParameter.into());
+        }
+        let info = config.supported_collaterals.iter()
+            .find(|x| x.mint == c.mint)
+            .ok_or(ErrorCode::UnsupportedCollateral)?;
+        let price = get_price_from_feed(
+            health::find_price_feed(feeds, &info.price_feed)?,
+            health::PRICE_MAX_AGE,
+            now,
+        )?;
+        let available = debt_account.collateral_balances.iter()
+            .find(|b| b.mint == c.mint)
+            .map_or(0, |b| b.amount);
+        legs.push(SeizureLeg {
+            price,
+            liquidation_bonus: effective_liquidation_bonus(
+                &config.liquidation_params,
+                info.liquidation_bonus,
+                debt_account.unhealthy_since_ts,
+                now,
+            ),
+            value: (available as u128) * (price as u128),
+            cap: available.min(c.max_amount),
+        });
+    }
+
+    let (repay_amount, amounts) = calculate_seizures(requested, borrow_price, &legs)?;
+
+    // The liquidator may never receive more than repaid value plus bonus.
+    let mut seized_value: u128 = 0;
+    let mut seized_value_at_par: u128 = 0;
+    let mut seizures = Vec::with_capacity(legs.len());
+    for ((leg, &amount), c) in legs.iter().zip(&amounts).zip(collaterals) {
+        let value = (amount as u128)
+            .checked_mul(leg.price as u128)
+            .ok_or(ErrorCode::MathOverflow)?;
+        seized_value = seized_value.checked_add(value).ok_or(ErrorCode::MathOverflow)?;
+        seized_value_at_par = seized_value_at_par
+            .checked_add(value * 10000 / (10000 + leg.liquidation_bonus as u128))
+            .ok_or(ErrorCode::MathOverflow)?;
+        seizures.push(Seizure {
+            mint: c.mint,
+            amount,
+            protocol_fee: protocol_fee(amount, leg.liquidation_bonus, config.liquidation_params.protocol_fee_bps),
+        });
+    }
+    if seized_value_at_par > (repay_amount as u128) * (borrow_price as u128) {
+        return Err(ErrorCode::ExcessiveSeizure.into());
+    }
+
+    let mut plan = LiquidationPlan {
+        borrow_mint,
+        owed,
+        repay_amount,
+        seizures,
+        bonus_value: seized_value - seized_value_at_par,
+        health_before,
+        health_after: HealthReport::default(),
+    };
+
+    let mut after = debt_account.clone();
+    apply_to_debt_account(&mut after, &plan, index)?;
+    plan.health_after = health::compute_health(config, &after, feeds, now)?;
+
+    if !liquidation_makes_progress(&plan, &after) {
+        return Err(ErrorCode::LiquidationWorsensHealth.into());
+    }
+
+    Ok(plan)
+}
+
+/// A liquidation must leave the account healthier, or else finish what it
+/// targets: repay the debt in full or empty every collateral it seizes from.
+/// Below roughly `(1 + bonus) * liquidation_threshold` every liquidation
+/// lowers the health factor, and one instruction only repays a single debt,
+/// so requiring the whole account to be closed would leave accounts with
+/// several positions stuck. Failing all of those it must at least reduce
+/// the shortfall of debt value over collateral value. `after` is the account
+/// with `plan` applied.
+fn liquidation_makes_progress(plan: &LiquidationPlan, after: &UserDebtAccount) -> bool {
+    let improves = plan.health_after.health_factor >= plan.health_before.health_factor;
+    let debt_repaid = plan.repay_amount == plan.owed;
+    let collateral_exhausted = !plan.seizures.is_empty()
+        && plan.seizures.iter().all(|s| {
+            after.collateral_balances.iter()
+                .find(|c| c.mint == s.mint)
+                .is_none_or(|c| c.amount == 0)
+        });
+    let shortfall = |report: &HealthReport| report.debt_value.saturating_sub(report.collateral_value);
+    let shortfall_reduced = shortfall(&plan.health_after) < shortfall(&plan.health_before);
+    improves || debt_repaid || collateral_exhausted || shortfall_reduced
+}
+
+fn apply_to_debt_account(debt_account: &mut UserDebtAccount, plan: &LiquidationPlan, index: u128) -> Result<()> {
+    let slot = debt_account.debt_balances.iter_mut()
+        .find(|d| d.borrow_mint == plan.borrow_mint)
+        .ok_or(ErrorCode::NoDebtToRepay)?;
+    slot.principal = plan.owed.checked_sub(plan.repay_amount).ok_or(ErrorCode::MathOverflow)?;
+    slot.interest_index_snapshot = index;
+
+    for seizure in &plan.seizures {
+        if let Some(c) = debt_account.collateral_balances.iter_mut().find(|c| c.mint == seizure.mint) {
+            c.amount = c.amount.checked_sub(seizure.amount).ok_or(ErrorCode::MathOverflow)?;
+        }
+    }
+    Ok(())
+}
+
+/// Writes `plan` to the protocol and the liquidated account. Token movements
+/// are left to the caller.
+pub fn apply_liquidation(
+    config: &mut ProtocolConfig,
+    debt_account: &mut UserDebtAccount,
+    plan: &LiquidationPlan,
+    now: i64,
+) -> Result<()> {
+    let asset = config.supported_borrows.iter_mut()
+        .find(|a| a.mint == plan.borrow_mint)
+        .ok_or(ErrorCode::UnsupportedBorrowAsset)?;
+    let index = accrue_global_index(asset, now as u64)?;
+    asset.total_borrows = asset.total_borrows.checked_sub(plan.repay_amount).ok_or(ErrorCode::MathOverflow)?;
+
+    apply_to_debt_account(debt_account, plan, index)?;
+    health::invalidate_cached_health(debt_account);
+    health::update_unhealthy_since(debt_account, &plan.health_after, now);
+
+    Ok(())
+}
+
+/// Moves `amount` out of the `[b"vault", mint]` PDA, signing for it.
+pub fn transfer_from_vault<'info>(
+    vault: &AccountInfo<'info>,
+    destination: &AccountInfo<'info>,
+    token_program: &AccountInfo<'info>,
+    mint: &Pubkey,
+    bump: u8,
+    amount: u64,
+) -> Result<()> {
+    let seeds = &[
+        b"vault".as_ref(),
+        mint.as_ref(),
+        &[bump],
+    ];
+    let signer = &[&seeds[..]];
+
+    let cpi_accounts = Transfer {
+        from: vault.clone(),
+        to: destination.clone(),
+        authority: vault.clone(),
+    };
+    let cpi_ctx = CpiContext::new_with_signer(token_program.clone(), cpi_accounts, signer);
+    token::transfer(cpi_ctx, amount)
+}
+
+/// Pays out `seizure` from its vault, splitting off the protocol fee.
+fn pay_seizure<'info>(
+    vault: &AccountInfo<'info>,
+    liquidator_account: &AccountInfo<'info>,
+    treasury_account: &AccountInfo<'info>,
+    token_program: &AccountInfo<'info>,
+    bump: u8,
+    seizure: &Seizure,
+) -> Result<()> {
+    let to_liquidator = seizure.amount - seizure.protocol_fee;
+    if to_liquidator > 0 {
+        transfer_from_vault(vault, liquidator_account, token_program, &seizure.mint, bump, to_liquidator)?;
+    }
+    if seizure.protocol_fee > 0 {
+        transfer_from_vault(vault, treasury_account, token_program, &seizure.mint, bump, seizure.protocol_fee)?;
+    }
+    Ok(())
+}
+
+/// Rejects the owner of `debt_account`, or its delegate, as liquidator.
+pub fn check_liquidator(debt_account: &UserDebtAccount, liquidator: &Pubkey) -> Result<()> {
+    if debt_account.owner == *liquidator || debt_account.delegate == Some(*liquidator) {
+        return Err(ErrorCode::SelfLiquidation.into());
+    }
+    Ok(())
+}
+
+/// Checks a treasury token account passed through `remaining_accounts`.
+fn check_treasury_account<'info>(account: &'info AccountInfo<'info>, config: &ProtocolConfig, mint: &Pubkey) -> Result<()> {
+    let token_account = Account::<TokenAccount>::try_from(account)?;
+    if token_account.owner != config.treasury || token_account.mint != *mint {
+        return Err(ErrorCode::InvalidTreasury.into());
+    }
+    Ok(())
+}
 
 #[derive(Accounts)]
 pub struct Liquidate<'info> {
@@ -18,6 +430,12 @@ pub struct Liquidate<'info> {
     pub liquidator_collateral_account: Account<'info, TokenAccount>,
     #[account(mut)]
     pub liquidator_borrow_account: Account<'info, TokenAccount>,
+    #[account(
+        mut,
+        constraint = treasury_collateral_account.owner == config.treasury @ ErrorCode::InvalidTreasury,
+        constraint = treasury_collateral_account.mint == collateral_mint.key() @ ErrorCode::InvalidTreasury
+    )]
+    pub treasury_collateral_account: Account<'info, TokenAccount>,
     #[account(mut, seeds = [b"config"], bump = config.bump)]
     pub config: Account<'info, ProtocolConfig>,
     #[account(
@@ -36,71 +454,348 @@ pub struct Liquidate<'info> {
     pub borrow_price_feed: AccountInfo<'info>,
 }
 
-pub fn liquidate(ctx: Context<Liquidate>, amount: u64) -> Result<()> {
-    let config = &ctx.accounts.config;
+pub fn liquidate<'info>(ctx: Context<'_, '_, 'info, 'info, Liquidate<'info>>, amount: u64) -> Result<()> {
+    let config = &mut ctx.accounts.config;
     let debt_account = &mut ctx.accounts.debt_account;
     let now = Clock::get()?.unix_timestamp;
+    check_liquidator(debt_account, &ctx.accounts.liquidator.key())?;
+    flashloan::check_vault_unlocked(config, &ctx.accounts.vault.key())?;
+    let collateral_mint_key = ctx.accounts.collateral_mint.key();
+    let borrow_mint_key = ctx.accounts.borrow_mint.key();
 
-
-    let collateral_info = config.supported_collaterals.iter().find(|x| x.mint == ctx.accounts.collateral_mint.key()).ok_or(ErrorCode::UnsupportedCollateral)?;
+    let collateral_info = config.supported_collaterals.iter().find(|x| x.mint == collateral_mint_key).ok_or(ErrorCode::UnsupportedCollateral)?;
     if collateral_info.price_feed != ctx.accounts.collateral_price_feed.key() {
         return Err(ErrorCode::InvalidPriceFeed.into());
     }
-    let borrow_info = config.supported_borrows.iter().find(|x| x.mint == ctx.accounts.borrow_mint.key()).ok_or(ErrorCode::UnsupportedBorrowAsset)?;
+    let borrow_info = config.supported_borrows.iter().find(|x| x.mint == borrow_mint_key).ok_or(ErrorCode::UnsupportedBorrowAsset)?;
     if borrow_info.price_feed != ctx.accounts.borrow_price_feed.key() {
         return Err(ErrorCode::InvalidPriceFeed.into());
     }
 
+    let mut feeds = vec![
+        ctx.accounts.collateral_price_feed.clone(),
+        ctx.accounts.borrow_price_feed.clone(),
+    ];
+    feeds.extend_from_slice(ctx.remaining_accounts);
+    let collaterals = [CollateralToSeize { mint: collateral_mint_key, max_amount: u64::MAX }];
+    let plan = plan_liquidation(config, debt_account, borrow_mint_key, &collaterals, amount, &feeds, now)?;
+    apply_liquidation(config, debt_account, &plan, now)?;
+
+    let cpi_accounts = Burn {
+        mint: ctx.accounts.borrow_mint.to_account_info(),
+        from: ctx.accounts.liquidator_borrow_account.to_account_info(),
+        authority: ctx.accounts.liquidator.to_account_info(),
+    };
+    let cpi_program = ctx.accounts.token_program.to_account_info();
+    let cpi_ctx = CpiContext::new(cpi_program, cpi_accounts);
+    token::burn(cpi_ctx, plan.repay_amount)?;
+
+    pay_seizure(
+        &ctx.accounts.vault.to_account_info(),
+        &ctx.accounts.liquidator_collateral_account.to_account_info(),
+        &ctx.accounts.treasury_collateral_account.to_account_info(),
+        &ctx.accounts.token_program.to_account_info(),
+        ctx.bumps.vault,
+        &plan.seizures[0],
+    )?;
+
+    Ok(())
+}
+
+#[derive(Accounts)]
+pub struct LiquidateMulti<'info> {
+    #[account(mut, seeds = [b"debt", user.key().as_ref()], bump = debt_account.bump)]
+    pub debt_account: Account<'info, UserDebtAccount>,
+    /// CHECK: Used only as a seed for deriving the debt_account PDA
+    pub user: AccountInfo<'info>,
+    #[account(mut)]
+    pub liquidator: Signer<'info>,
+    #[account(mut)]
+    pub liquidator_borrow_account: Account<'info, TokenAccount>,
+    #[account(mut, seeds = [b"config"], bump = config.bump)]
+    pub config: Account<'info, ProtocolConfig>,
+    #[account(mut)]
+    pub borrow_mint: Account<'info, Mint>,
+    pub token_program: Program<'info, Token>,
+}
+
+/// Repays one debt and seizes from several collaterals in proportion to
+/// their value. `remaining_accounts` starts with a `(vault, liquidator token
+/// account, treasury token account)` triple per entry of `collaterals`, followed by the price feeds of
+/// every position. Each `max_amount` caps what is taken from that collateral;
+/// the repay amount shrinks to fit rather than the call failing.
+pub fn liquidate_multi<'info>(
+    ctx: Context<'_, '_, 'info, 'info, LiquidateMulti<'info>>,
+    amount: u64,
+    collaterals: Vec<CollateralToSeize>,
+) -> Result<()> {
+    let config = &mut ctx.accounts.config;
+    let debt_account = &mut ctx.accounts.debt_account;
+    let now = Clock::get()?.unix_timestamp;
+    let borrow_mint_key = ctx.accounts.borrow_mint.key();
+    check_liquidator(debt_account, &ctx.accounts.liquidator.key())?;
+
+    if ctx.remaining_accounts.len() < collaterals.len() * 3 {
+        return Err(anchor_lang::error::ErrorCode::AccountNotEnoughKeys.into());
+    }
+    let (seizure_accounts, feeds) = ctx.remaining_accounts.split_at(collaterals.len() * 3);
+
+    let plan = plan_liquidation(config, debt_account, borrow_mint_key, &collaterals, amount, feeds, now)?;
+    apply_liquidation(config, debt_account, &plan, now)?;
 
-    let mut total_collateral_value: u128 = 0;
-    let mut total_debt_value: u128 = 0;
-    let mut weighted_threshold_numerator: u128 = 0;
+    let cpi_accounts = Burn {
+        mint: ctx.accounts.borrow_mint.to_account_info(),
+        from: ctx.accounts.liquidator_borrow_account.to_account_info(),
+        authority: ctx.accounts.liquidator.to_account_info(),
+    };
+    let cpi_program = ctx.accounts.token_program.to_account_info();
+    let cpi_ctx = CpiContext::new(cpi_program, cpi_accounts);
+    token::burn(cpi_ctx, plan.repay_amount)?;
 
-    for c in &debt_account.collateral_balances {
-        if let Some(info) = config.supported_collaterals.iter().find(|x| x.mint == c.mint) {
-            let price_feed = if info.mint == ctx.accounts.collateral_mint.key() {
-                &ctx.accounts.collateral_price_feed
-            } else {
-                &ctx.accounts.borrow_price_feed
-            };
-            let price = get_price_from_feed(price_feed, 60, now)?;
-            let value = (c.amount as u128) * (price as u128);
-            total_collateral_value += value;
-            weighted_threshold_numerator += value * (info.liquidation_threshold as u128);
+    for (seizure, accounts) in plan.seizures.iter().zip(seizure_accounts.chunks(3)) {
+        let (vault_key, bump) = Pubkey::find_program_address(&[b"vault", seizure.mint.as_ref()], &crate::ID);
+        if accounts[0].key() != vault_key {
+            return Err(ErrorCode::InvalidVault.into());
         }
+        flashloan::check_vault_unlocked(config, &vault_key)?;
+        check_treasury_account(&accounts[2], config, &seizure.mint)?;
+        pay_seizure(
+            &accounts[0],
+            &accounts[1],
+            &accounts[2],
+            &ctx.accounts.token_program.to_account_info(),
+            bump,
+            seizure,
+        )?;
     }
 
-    for d in &debt_account.debt_balances {
-        if let Some(info) = config.supported_borrows.iter().find(|x| x.mint == d.borrow_mint) {
-            let price = get_price_from_feed(&ctx.accounts.borrow_price_feed, 60, now)?;
-            let owed = calculate_owed_amount(d.principal, d.interest_index_snapshot, info.global_index)?;
-            let value = (owed as u128) * (price as u128);
-            total_debt_value += value;
+    Ok(())
+}
+
+/// Number of `remaining_accounts` consumed by each `LiquidationRequest`.
+pub const ACCOUNTS_PER_REQUEST: usize = 6;
+
+/// Per-request result of `liquidate_many`. Healthy accounts are reported
+/// with `liquidated == false` and zero amounts.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct LiquidationOutcome {
+    pub user: Pubkey,
+    pub liquidated: bool,
+    pub repay_amount: u64,
+    pub seized_amount: u64,
+}
+
+#[derive(Accounts)]
+pub struct LiquidateMany<'info> {
+    #[account(mut)]
+    pub liquidator: Signer<'info>,
+    #[account(mut, seeds = [b"config"], bump = config.bump)]
+    pub config: Account<'info, ProtocolConfig>,
+    pub token_program: Program<'info, Token>,
+}
+
+/// Liquidates several accounts in one instruction. Each request takes
+/// `ACCOUNTS_PER_REQUEST` remaining accounts, in order: debt account, the
+/// collateral vault, the liquidator's collateral token account, the
+/// treasury's collateral token account, the borrow mint and the liquidator's
+/// borrow token account. Price feeds follow the
+/// last request's accounts.
+pub fn liquidate_many<'info>(
+    ctx: Context<'_, '_, 'info, 'info, LiquidateMany<'info>>,
+    requests: Vec<LiquidationRequest>,
+) -> Result<Vec<LiquidationOutcome>> {
+    let config = &mut ctx.accounts.config;
+    let now = Clock::get()?.unix_timestamp;
+    let token_program = ctx.accounts.token_program.to_account_info();
+
+    let request_accounts_len = requests.len()
+        .checked_mul(ACCOUNTS_PER_REQUEST)
+        .ok_or(ErrorCode::MathOverflow)?;
+    if ctx.remaining_accounts.len() < request_accounts_len {
+        return Err(anchor_lang::error::ErrorCode::AccountNotEnoughKeys.into());
+    }
+    let (request_accounts, feeds) = ctx.remaining_accounts.split_at(request_accounts_len);
+
+    let mut outcomes = Vec::with_capacity(requests.len());
+    for (request, accounts) in requests.iter().zip(request_accounts.chunks(ACCOUNTS_PER_REQUEST)) {
+        let [debt_info, vault, liquidator_collateral_account, treasury_collateral_account, borrow_mint, liquidator_borrow_account] = accounts else {
+            return Err(anchor_lang::error::ErrorCode::AccountNotEnoughKeys.into());
+        };
+
+        let (debt_key, _) = Pubkey::find_program_address(&[b"debt", request.user.as_ref()], &crate::ID);
+        if debt_info.key() != debt_key {
+            return Err(ErrorCode::Unauthorized.into());
+        }
+        let (vault_key, vault_bump) = Pubkey::find_program_address(&[b"vault", request.collateral_mint.as_ref()], &crate::ID);
+        if vault.key() != vault_key {
+            return Err(ErrorCode::InvalidVault.into());
         }
+        flashloan::check_vault_unlocked(config, &vault_key)?;
+        if borrow_mint.key() != request.borrow_mint {
+            return Err(ErrorCode::UnsupportedBorrowAsset.into());
+        }
+        check_treasury_account(treasury_collateral_account, config, &request.collateral_mint)?;
+
+        // Deserialized per request so that a debt account listed twice sees
+        // the result of the earlier liquidation.
+        let mut debt_account = Account::<UserDebtAccount>::try_from(debt_info)?;
+        check_liquidator(&debt_account, &ctx.accounts.liquidator.key())?;
+        let collaterals = [CollateralToSeize { mint: request.collateral_mint, max_amount: u64::MAX }];
+        let plan = match plan_liquidation(config, &debt_account, request.borrow_mint, &collaterals, request.amount, feeds, now) {
+            Ok(plan) => plan,
+            // An account this batch cannot liquidate as requested is skipped
+            // rather than failing everyone else's.
+            Err(err) if err == ErrorCode::AccountHealthy.into() || err == ErrorCode::LiquidationWorsensHealth.into() => {
+                outcomes.push(LiquidationOutcome {
+                    user: request.user,
+                    liquidated: false,
+                    repay_amount: 0,
+                    seized_amount: 0,
+                });
+                continue;
+            }
+            Err(err) => return Err(err),
+        };
+        apply_liquidation(config, &mut debt_account, &plan, now)?;
+        debt_account.exit(&crate::ID)?;
+
+        let cpi_accounts = Burn {
+            mint: borrow_mint.clone(),
+            from: liquidator_borrow_account.clone(),
+            authority: ctx.accounts.liquidator.to_account_info(),
+        };
+        let cpi_ctx = CpiContext::new(token_program.clone(), cpi_accounts);
+        token::burn(cpi_ctx, plan.repay_amount)?;
+
+        pay_seizure(
+            vault,
+            liquidator_collateral_account,
+            treasury_collateral_account,
+            &token_program,
+            vault_bump,
+            &plan.seizures[0],
+        )?;
+
+        outcomes.push(LiquidationOutcome {
+            user: request.user,
+            liquidated: true,
+            repay_amount: plan.repay_amount,
+            seized_amount: plan.seizures[0].amount,
+        });
+    }
+
+    Ok(outcomes)
+}
+
+#[derive(Accounts)]
+pub struct FlashLiquidate<'info> {
+    #[account(mut, seeds = [b"debt", user.key().as_ref()], bump = debt_account.bump)]
+    pub debt_account: Account<'info, UserDebtAccount>,
+    /// CHECK: Used only as a seed for deriving the debt_account PDA
+    pub user: AccountInfo<'info>,
+    #[account(mut)]
+    pub liquidator: Signer<'info>,
+    #[account(mut)]
+    pub liquidator_collateral_account: Account<'info, TokenAccount>,
+    #[account(mut)]
+    pub liquidator_borrow_account: Account<'info, TokenAccount>,
+    #[account(
+        mut,
+        constraint = treasury_collateral_account.owner == config.treasury @ ErrorCode::InvalidTreasury,
+        constraint = treasury_collateral_account.mint == collateral_mint.key() @ ErrorCode::InvalidTreasury
+    )]
+    pub treasury_collateral_account: Account<'info, TokenAccount>,
+    #[account(mut, seeds = [b"config"], bump = config.bump)]
+    pub config: Account<'info, ProtocolConfig>,
+    #[account(
+        mut,
+        seeds = [b"vault", collateral_mint.key().as_ref()],
+        bump
+    )]
+    pub vault: Account<'info, TokenAccount>,
+    pub collateral_mint: Account<'info, Mint>,
+    #[account(mut)]
+    pub borrow_mint: Account<'info, Mint>,
+    pub token_program: Program<'info, Token>,
+    /// CHECK: Verified in instruction
+    pub collateral_price_feed: AccountInfo<'info>,
+    /// CHECK: Verified in instruction
+    pub borrow_price_feed: AccountInfo<'info>,
+    /// CHECK: The program that will be called via CPI
+    pub target_program: AccountInfo<'info>,
+}
+
+/// Liquidation without holding the debt asset upfront. The seized collateral
+/// is sent to `liquidator_collateral_account` first, then `target_program` is
+/// invoked with `instruction_data` (e.g. a swap into the debt asset), and the
+/// repay amount is finally burned from `liquidator_borrow_account`. The first
+/// `feed_count` remaining accounts are price feeds; the rest are passed to
+/// the callback.
+pub fn flash_liquidate<'info>(
+    ctx: Context<'_, '_, 'info, 'info, FlashLiquidate<'info>>,
+    amount: u64,
+    feed_count: u8,
+    instruction_data: Vec<u8>,
+) -> Result<()> {
+    let config = &mut ctx.accounts.config;
+    let debt_account = &mut ctx.accounts.debt_account;
+    let now = Clock::get()?.unix_timestamp;
+    check_liquidator(debt_account, &ctx.accounts.liquidator.key())?;
+    flashloan::check_vault_unlocked(config, &ctx.accounts.vault.key())?;
+    let collateral_mint_key = ctx.accounts.collateral_mint.key();
+    let borrow_mint_key = ctx.accounts.borrow_mint.key();
+
+    let collateral_info = config.supported_collaterals.iter().find(|x| x.mint == collateral_mint_key).ok_or(ErrorCode::UnsupportedCollateral)?;
+    if collateral_info.price_feed != ctx.accounts.collateral_price_feed.key() {
+        return Err(ErrorCode::InvalidPriceFeed.into());
+    }
+    let borrow_info = config.supported_borrows.iter().find(|x| x.mint == borrow_mint_key).ok_or(ErrorCode::UnsupportedBorrowAsset)?;
+    if borrow_info.price_feed != ctx.accounts.borrow_price_feed.key() {
+        return Err(ErrorCode::InvalidPriceFeed.into());
     }
-    
-    let weighted_threshold = if total_collateral_value > 0 {
-        weighted_threshold_numerator / total_collateral_value
-    } else {
-        0
-    };
 
-    if total_debt_value * 10000 < total_collateral_value * weighted_threshold {
-        return Err(ErrorCode::Unauthorized.into());
+    let feed_count = feed_count as usize;
+    if ctx.remaining_accounts.len() < feed_count {
+        return Err(anchor_lang::error::ErrorCode::AccountNotEnoughKeys.into());
     }
+    let (extra_feeds, callback_accounts) = ctx.remaining_accounts.split_at(feed_count);
+    let mut feeds = vec![
+        ctx.accounts.collateral_price_feed.clone(),
+        ctx.accounts.borrow_price_feed.clone(),
+    ];
+    feeds.extend_from_slice(extra_feeds);
 
-    let debt_balance = debt_account.debt_balances.iter()
-        .find(|d| d.borrow_mint == ctx.accounts.borrow_mint.key())
-        .ok_or(ErrorCode::NoDebtToRepay)?;
-    
-    let owed = calculate_owed_amount(debt_balance.principal, debt_balance.interest_index_snapshot, borrow_info.global_index)?;
-    
-    let max_liquidatable = (owed as u128) * (MAX_LIQUIDATION_CLOSE_FACTOR as u128) / 10000;
-    let actual_amount = if (amount as u128) > max_liquidatable {
-        max_liquidatable as u64
-    } else {
-        amount
-    };
+    let collaterals = [CollateralToSeize { mint: collateral_mint_key, max_amount: u64::MAX }];
+    let plan = plan_liquidation(config, debt_account, borrow_mint_key, &collaterals, amount, &feeds, now)?;
+    apply_liquidation(config, debt_account, &plan, now)?;
+
+    // Persist the liquidation before handing control to the callback, and
+    // reload afterwards so nothing it wrote is overwritten on exit.
+    ctx.accounts.config.exit(&crate::ID)?;
+    ctx.accounts.debt_account.exit(&crate::ID)?;
+
+    pay_seizure(
+        &ctx.accounts.vault.to_account_info(),
+        &ctx.accounts.liquidator_collateral_account.to_account_info(),
+        &ctx.accounts.treasury_collateral_account.to_account_info(),
+        &ctx.accounts.token_program.to_account_info(),
+        ctx.bumps.vault,
+        &plan.seizures[0],
+    )?;
+
+    flashloan::invoke_callback(
+        &ctx.accounts.target_program,
+        callback_accounts,
+        instruction_data,
+        &ctx.accounts.vault.key(),
+    )?;
+
+    ctx.accounts.config.reload()?;
+    ctx.accounts.debt_account.reload()?;
+    ctx.accounts.liquidator_borrow_account.reload()?;
+    if ctx.accounts.liquidator_borrow_account.amount < plan.repay_amount {
+        return Err(ErrorCode::FlashReplyError.into());
+    }
 
     let cpi_accounts = Burn {
         mint: ctx.accounts.borrow_mint.to_account_info(),
@@ -109,25 +804,142 @@ pub fn liquidate(ctx: Context<Liquidate>, amount: u64) -> Result<()> {
     };
     let cpi_program = ctx.accounts.token_program.to_account_info();
     let cpi_ctx = CpiContext::new(cpi_program, cpi_accounts);
-    token::burn(cpi_ctx, actual_amount)?;
+    token::burn(cpi_ctx, plan.repay_amount)?;
+
+    Ok(())
+}
+
+#[derive(Accounts)]
+pub struct MarkUnhealthy<'info> {
+    #[account(mut, seeds = [b"debt", user.key().as_ref()], bump = debt_account.bump)]
+    pub debt_account: Account<'info, UserDebtAccount>,
+    /// CHECK: Used only as a seed for deriving the debt_account PDA
+    pub user: AccountInfo<'info>,
+    #[account(seeds = [b"config"], bump = config.bump)]
+    pub config: Account<'info, ProtocolConfig>,
+}
+
+/// Permissionless crank. Starts the liquidation bonus ramp when the account
+/// is liquidatable and resets it when it is not. Price feeds for every
+/// position are passed in `remaining_accounts`.
+pub fn mark_unhealthy<'info>(ctx: Context<'_, '_, 'info, 'info, MarkUnhealthy<'info>>) -> Result<()> {
+    let now = Clock::get()?.unix_timestamp;
+    let report = health::compute_health(&ctx.accounts.config, &ctx.accounts.debt_account, ctx.remaining_accounts, now)?;
+    health::update_unhealthy_since(&mut ctx.accounts.debt_account, &report, now);
+    Ok(())
+}
+
+#[derive(Accounts)]
+#[instruction(borrow_mint: Pubkey)]
+pub struct WriteOffBadDebt<'info> {
+    #[account(mut, seeds = [b"debt", user.key().as_ref()], bump = debt_account.bump)]
+    pub debt_account: Account<'info, UserDebtAccount>,
+    /// CHECK: Used only as a seed for deriving the debt_account PDA
+    pub user: AccountInfo<'info>,
+    #[account(mut, seeds = [b"config"], bump = config.bump)]
+    pub config: Account<'info, ProtocolConfig>,
+    #[account(mut, address = borrow_mint)]
+    pub mint: Account<'info, Mint>,
+    /// CHECK: The asset's vault PDA, which holds its reserves.
+    #[account(mut, seeds = [b"vault", borrow_mint.as_ref()], bump)]
+    pub vault: UncheckedAccount<'info>,
+    /// CHECK: The asset's insurance fund PDA. It may be uninitialized, in
+    /// which case nothing is slashed; requiring the address stops callers
+    /// from skipping an existing fund.
+    #[account(seeds = [b"insurance", borrow_mint.as_ref()], bump)]
+    pub insurance_fund: UncheckedAccount<'info>,
+    /// CHECK: The insurance fund's vault PDA, only used when the fund exists.
+    #[account(mut, seeds = [b"insurance_vault", borrow_mint.as_ref()], bump)]
+    pub insurance_vault: UncheckedAccount<'info>,
+    pub token_program: Program<'info, Token>,
+}
+
+/// Permissionless. Once every collateral balance of an account is zero, any
+/// debt left in `borrow_mint` can never be repaid by liquidation; this zeroes
+/// it and burns the same amount out of the asset's reserves and insurance
+/// fund, as split by `absorb_bad_debt`.
+pub fn write_off_bad_debt(ctx: Context<WriteOffBadDebt>, borrow_mint: Pubkey) -> Result<()> {
+    let config = &mut ctx.accounts.config;
+    let debt_account = &mut ctx.accounts.debt_account;
+    let now = Clock::get()?.unix_timestamp as u64;
 
-    if let Some(d) = debt_account.debt_balances.iter_mut().find(|d| d.borrow_mint == ctx.accounts.borrow_mint.key()) {
-        d.principal = d.principal.checked_sub(actual_amount).ok_or(ErrorCode::MathOverflow)?;
+    if debt_account.collateral_balances.iter().any(|c| c.amount > 0) {
+        return Err(ErrorCode::CollateralNotExhausted.into());
     }
 
-    let _borrow_info = config.supported_borrows.iter().find(|x| x.mint == ctx.accounts.borrow_mint.key()).ok_or(ErrorCode::UnsupportedBorrowAsset)?;
-    let collateral_info = config.supported_collaterals.iter().find(|x| x.mint == ctx.accounts.collateral_mint.key()).ok_or(ErrorCode::UnsupportedCollateral)?;
+    let asset = config.supported_borrows.iter_mut()
+        .find(|a| a.mint == borrow_mint)
+        .ok_or(ErrorCode::UnsupportedBorrowAsset)?;
+    accrue_global_index(asset, now)?;
 
-    let borrow_price = get_price_from_feed(&ctx.accounts.borrow_price_feed, 60, now)?;
-    let collateral_price = get_price_from_feed(&ctx.accounts.collateral_price_feed, 60, now)?;
+    let slot = debt_account.debt_balances.iter_mut()
+        .find(|d| d.borrow_mint == borrow_mint)
+        .ok_or(ErrorCode::NoDebtToRepay)?;
+    let owed = calculate_owed_amount(slot.principal, slot.interest_index_snapshot, asset.global_index)?;
+    if owed == 0 {
+        return Err(ErrorCode::NoDebtToRepay.into());
+    }
+    slot.principal = 0;
+    slot.interest_index_snapshot = asset.global_index;
 
+    let insurance_fund = &ctx.accounts.insurance_fund;
+    let insurance_vault = &ctx.accounts.insurance_vault;
+    let insurance_available = if insurance_fund.owner == &crate::ID && !insurance_fund.data_is_empty() {
+        let fund = InsuranceFund::try_deserialize(&mut &insurance_fund.data.borrow()[..])?;
+        let vault = TokenAccount::try_deserialize(&mut &insurance_vault.data.borrow()[..])?;
+        insurance::max_slashable(&fund, vault.amount)
+    } else {
+        0
+    };
 
-    let borrow_value = (actual_amount as u128) * (borrow_price as u128);
-    let collateral_value_to_seize = borrow_value * (10000 + collateral_info.liquidation_bonus as u128) / 10000;
-    let collateral_amount_to_seize = collateral_value_to_seize / (collateral_price as u128);
+    let (from_reserves, slashed) = absorb_bad_debt(asset, owed, insurance_available)?;
+    if from_reserves > 0 {
+        burn_from_vault(
+            &ctx.accounts.vault.to_account_info(),
+            &ctx.accounts.mint.to_account_info(),
+            &ctx.accounts.token_program.to_account_info(),
+            ctx.bumps.vault,
+            from_reserves,
+        )?;
+    }
+    if slashed > 0 {
+        insurance::slash_insurance(
+            &insurance_vault.to_account_info(),
+            &ctx.accounts.mint.to_account_info(),
+            &ctx.accounts.token_program.to_account_info(),
+            slashed,
+        )?;
+    }
+    Ok(())
+}
 
-    let mint_key = ctx.accounts.collateral_mint.key();
-    let bump = ctx.bumps.vault;
+/// Removes `amount` of unrecoverable debt from `asset`. The loss is covered
+/// from `reserves` first, then from up to `insurance_available` of the
+/// insurance fund; a loss the two cannot cover is rejected and leaves `asset`
+/// untouched. Returns the amounts the caller must burn from the reserves and
+/// slash from the insurance fund.
+pub fn absorb_bad_debt(asset: &mut BorrowAssetInfo, amount: u64, insurance_available: u64) -> Result<(u64, u64)> {
+    let from_reserves = amount.min(asset.reserves);
+    let from_insurance = amount - from_reserves;
+    if from_insurance > insurance_available {
+        return Err(ErrorCode::BadDebtNotCovered.into());
+    }
+
+    asset.total_borrows = asset.total_borrows.checked_sub(amount).ok_or(ErrorCode::MathOverflow)?;
+    asset.bad_debt = asset.bad_debt.checked_add(amount).ok_or(ErrorCode::MathOverflow)?;
+    asset.reserves -= from_reserves;
+    Ok((from_reserves, from_insurance))
+}
+
+/// Burns `amount` of reserves held by the vault PDA for `mint`.
+fn burn_from_vault<'info>(
+    vault: &AccountInfo<'info>,
+    mint: &AccountInfo<'info>,
+    token_program: &AccountInfo<'info>,
+    bump: u8,
+    amount: u64,
+) -> Result<()> {
+    let mint_key = mint.key();
     let seeds = &[
         b"vault".as_ref(),
         mint_key.as_ref(),
@@ -135,17 +947,309 @@ pub fn liquidate(ctx: Context<Liquidate>, amount: u64) -> Result<()> {
     ];
     let signer = &[&seeds[..]];
 
-    let cpi_accounts_transfer = Transfer {
-        from: ctx.accounts.vault.to_account_info(),
-        to: ctx.accounts.liquidator_collateral_account.to_account_info(),
-        authority: ctx.accounts.vault.to_account_info(),
+    let cpi_accounts = Burn {
+        mint: mint.clone(),
+        from: vault.clone(),
+        authority: vault.clone(),
     };
-    let cpi_ctx_transfer = CpiContext::new_with_signer(ctx.accounts.token_program.to_account_info(), cpi_accounts_transfer, signer);
-    token::transfer(cpi_ctx_transfer, collateral_amount_to_seize as u64)?;
+    let cpi_ctx = CpiContext::new_with_signer(token_program.clone(), cpi_accounts, signer);
+    token::burn(cpi_ctx, amount)
+}
 
-    if let Some(c) = debt_account.collateral_balances.iter_mut().find(|c| c.mint == ctx.accounts.collateral_mint.key()) {
-        c.amount = c.amount.checked_sub(collateral_amount_to_seize as u64).ok_or(ErrorCode::MathOverflow)?;
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::test_fixtures::{borrow_asset, collateral_info, price_account_data, protocol_config};
+
+    fn leg(price: u64, liquidation_bonus: u64, available: u64) -> SeizureLeg {
+        SeizureLeg {
+            price,
+            liquidation_bonus,
+            value: (available as u128) * (price as u128),
+            cap: available,
+        }
     }
 
-    Ok(())
+    #[test]
+    fn test_calculate_seizures_unclamped() {
+        // 100 units at $2 plus a 5% bonus, paid in collateral worth $1.
+        let (repay, seized) = calculate_seizures(100, 2_000_000, &[leg(1_000_000, 500, 1_000)]).unwrap();
+        assert_eq!(repay, 100);
+        assert_eq!(seized, vec![210]);
+    }
+
+    #[test]
+    fn test_calculate_seizures_clamped_to_available() {
+        let (repay, seized) = calculate_seizures(100, 2_000_000, &[leg(1_000_000, 500, 105)]).unwrap();
+        assert_eq!(seized, vec![105]);
+        assert_eq!(repay, 50);
+
+        // Rounds the repay amount up when the clamped seizure isn't exact.
+        let (repay, seized) = calculate_seizures(100, 2_000_000, &[leg(1_000_000, 500, 100)]).unwrap();
+        assert_eq!(seized, vec![100]);
+        assert_eq!(repay, 48);
+    }
+
+    #[test]
+    fn test_calculate_seizures_requires_collateral() {
+        assert!(calculate_seizures(100, 1_000_000, &[leg(1_000_000, 500, 0)]).is_err());
+    }
+
+    #[test]
+    fn test_calculate_seizures_proportional() {
+        // $3000 and $1000 of collateral split a $400 repayment 3:1.
+        let legs = [leg(1_000_000, 0, 3_000), leg(2_000_000, 1000, 500)];
+        let (repay, seized) = calculate_seizures(400, 1_000_000, &legs).unwrap();
+        assert_eq!(repay, 400);
+        assert_eq!(seized, vec![300, 55]);
+    }
+
+    #[test]
+    fn test_calculate_seizures_honors_max_amount() {
+        let mut legs = [leg(1_000_000, 0, 3_000), leg(2_000_000, 0, 500)];
+        legs[1].cap = 25;
+        let (repay, seized) = calculate_seizures(400, 1_000_000, &legs).unwrap();
+        assert_eq!(repay, 200);
+        assert_eq!(seized, vec![150, 25]);
+    }
+
+    #[test]
+    fn test_effective_liquidation_bonus_ramp() {
+        let params = LiquidationParams {
+            min_liquidation_bonus: 200,
+            bonus_ramp_duration: 100,
+            ..liquidation_params()
+        };
+        assert_eq!(effective_liquidation_bonus(&params, 1000, 0, 5_000), 200);
+        assert_eq!(effective_liquidation_bonus(&params, 1000, 5_000, 5_000), 200);
+        assert_eq!(effective_liquidation_bonus(&params, 1000, 5_000, 5_050), 600);
+        assert_eq!(effective_liquidation_bonus(&params, 1000, 5_000, 5_200), 1000);
+        // The minimum never exceeds the collateral's own bonus.
+        assert_eq!(effective_liquidation_bonus(&params, 100, 5_000, 5_000), 100);
+
+        assert_eq!(effective_liquidation_bonus(&liquidation_params(), 1000, 0, 5_000), 1000);
+    }
+
+    fn liquidation_params() -> LiquidationParams {
+        LiquidationParams {
+            min_liquidation_bonus: 0,
+            bonus_ramp_duration: 0,
+            close_factor: DEFAULT_CLOSE_FACTOR,
+            full_liquidation_health_factor: 0,
+            dust_debt_value: 0,
+            protocol_fee_bps: 0,
+        }
+    }
+
+    #[test]
+    fn test_effective_close_factor() {
+        let params = LiquidationParams {
+            full_liquidation_health_factor: (health::HEALTH_FACTOR_SCALE * 9 / 10) as u64,
+            dust_debt_value: 1_000,
+            ..liquidation_params()
+        };
+        let hf = health::HEALTH_FACTOR_SCALE * 95 / 100;
+        assert_eq!(effective_close_factor(&params, hf, 5_000), DEFAULT_CLOSE_FACTOR);
+        assert_eq!(effective_close_factor(&params, hf, 1_000), 10000);
+        assert_eq!(effective_close_factor(&params, health::HEALTH_FACTOR_SCALE * 8 / 10, 5_000), 10000);
+        assert_eq!(effective_close_factor(&liquidation_params(), hf, 5_000), DEFAULT_CLOSE_FACTOR);
+    }
+
+    #[test]
+    fn test_absorb_bad_debt_uses_reserves_first() {
+        let mut asset = BorrowAssetInfo { reserves: 100, total_deposits: 1_000, total_borrows: 1_000, ..borrow_asset() };
+        assert_eq!(absorb_bad_debt(&mut asset, 60, 50).unwrap(), (60, 0));
+        assert_eq!(asset.reserves, 40);
+        assert_eq!(asset.total_deposits, 1_000);
+        assert_eq!(asset.total_borrows, 940);
+        assert_eq!(asset.bad_debt, 60);
+    }
+
+    #[test]
+    fn test_absorb_bad_debt_slashes_insurance_after_reserves() {
+        let mut asset = BorrowAssetInfo { reserves: 100, total_deposits: 1_000, total_borrows: 1_000, ..borrow_asset() };
+        assert_eq!(absorb_bad_debt(&mut asset, 300, 200).unwrap(), (100, 200));
+        assert_eq!(asset.reserves, 0);
+        assert_eq!(asset.total_borrows, 700);
+        assert_eq!(asset.bad_debt, 300);
+    }
+
+    #[test]
+    fn test_absorb_bad_debt_rejects_uncovered_loss() {
+        let mut asset = BorrowAssetInfo { reserves: 100, total_deposits: 1_000, total_borrows: 1_000, ..borrow_asset() };
+        assert!(absorb_bad_debt(&mut asset, 300, 150).is_err());
+        assert!(absorb_bad_debt(&mut asset, 300, 0).is_err());
+        assert_eq!(asset.reserves, 100);
+        assert_eq!(asset.total_borrows, 1_000);
+        assert_eq!(asset.bad_debt, 0);
+    }
+
+    #[test]
+    fn test_protocol_fee_takes_share_of_bonus_only() {
+        // 210 seized at a 5% bonus holds 10 of bonus.
+        assert_eq!(protocol_fee(210, 500, 0), 0);
+        assert_eq!(protocol_fee(210, 500, 5000), 5);
+        assert_eq!(protocol_fee(210, 500, 10000), 10);
+        assert_eq!(protocol_fee(210, 0, 10000), 0);
+    }
+
+    const NOW: i64 = 1_000;
+
+    /// Plans a liquidation of `amount` on an account holding 1000 of a
+    /// collateral (80% threshold, 10% bonus) against `debt`, both priced at 1.
+    fn plan_for_debt(debt: u64, amount: u64, full_liquidation_health_factor: u64) -> Result<LiquidationPlan> {
+        plan_for_account(&[1_000], &[debt], amount, full_liquidation_health_factor)
+    }
+
+    /// Plans a liquidation of `amount` of the first debt, seizing from the
+    /// first collateral, on an account holding `collaterals` of collaterals
+    /// like `plan_for_debt`'s and `debts` of separate borrow assets, all
+    /// priced at 1.
+    fn plan_for_account(
+        collaterals: &[u64],
+        debts: &[u64],
+        amount: u64,
+        full_liquidation_health_factor: u64,
+    ) -> Result<LiquidationPlan> {
+        let mut config = protocol_config(Vec::new());
+        config.liquidation_params.full_liquidation_health_factor = full_liquidation_health_factor;
+        let mut account = UserDebtAccount {
+            owner: Pubkey::default(),
+            collateral_balances: Vec::new(),
+            debt_balances: Vec::new(),
+            accumulated_points: 0,
+            last_points_update_ts: 0,
+            referrer: None,
+            bump: 0,
+            cached_health: HealthReport::default(),
+            health_refresh_slot: 0,
+            unhealthy_since_ts: 0,
+            delegate: None,
+        };
+        let mut feed_keys = Vec::new();
+        for &amount in collaterals {
+            let (mint, feed) = (Pubkey::new_unique(), Pubkey::new_unique());
+            config.supported_collaterals.push(collateral_info(mint, feed));
+            account.collateral_balances.push(CollateralBalance { mint, amount });
+            feed_keys.push(feed);
+        }
+        for &debt in debts {
+            let (mint, feed) = (Pubkey::new_unique(), Pubkey::new_unique());
+            config.supported_borrows.push(BorrowAssetInfo {
+                mint,
+                price_feed: feed,
+                total_borrows: debt,
+                last_update_ts: NOW as u64,
+                ..borrow_asset()
+            });
+            account.debt_balances.push(DebtBalance { borrow_mint: mint, principal: debt, interest_index_snapshot: INDEX_SCALE });
+            feed_keys.push(feed);
+        }
+
+        let owner = Pubkey::default();
+        let mut lamports = vec![0; feed_keys.len()];
+        let mut data: Vec<_> = feed_keys.iter().map(|_| price_account_data(1, NOW)).collect();
+        let feeds: Vec<_> = feed_keys.iter().zip(lamports.iter_mut()).zip(data.iter_mut())
+            .map(|((key, lamports), data)| AccountInfo::new(key, false, false, lamports, data, &owner, false, 0))
+            .collect();
+        let seize = [CollateralToSeize { mint: account.collateral_balances[0].mint, max_amount: u64::MAX }];
+        let borrow_mint = account.debt_balances[0].borrow_mint;
+        plan_liquidation(&config, &account, borrow_mint, &seize, amount, &feeds, NOW)
+    }
+
+    #[test]
+    fn test_partial_liquidation_improving_health_keeps_close_factor() {
+        // Health factor 800 / 842 = 0.95.
+        let plan = plan_for_debt(842, u64::MAX, DEFAULT_FULL_LIQUIDATION_HEALTH_FACTOR).unwrap();
+        assert_eq!(plan.repay_amount, 421);
+        assert_eq!(plan.seizures[0].amount, 463);
+        assert!(plan.health_after.health_factor > plan.health_before.health_factor);
+    }
+
+    #[test]
+    fn test_worsening_partial_liquidation_falls_back_to_full_close_factor() {
+        // Health factor 0.8, under (1 + 10%) * 80%: repaying half would lower
+        // it, so the whole collateral is taken instead.
+        let full_below = (health::HEALTH_FACTOR_SCALE / 2) as u64;
+        let plan = plan_for_debt(1_000, u64::MAX, full_below).unwrap();
+        assert_eq!(plan.seizures[0].amount, 1_000);
+        assert_eq!(plan.repay_amount, 910);
+        assert_eq!(plan.health_after.collateral_value, 0);
+    }
+
+    #[test]
+    fn test_liquidation_worsening_health_without_closing_is_rejected() {
+        let full_below = (health::HEALTH_FACTOR_SCALE / 2) as u64;
+        assert_eq!(
+            plan_for_debt(1_000, 100, full_below).unwrap_err(),
+            ErrorCode::LiquidationWorsensHealth.into()
+        );
+    }
+
+    #[test]
+    fn test_repaying_one_of_two_debts_in_full_is_allowed() {
+        // Health factor 720 / 840 = 0.857, under (1 + 10%) * 80%. Repaying
+        // either debt in full lowers it, and no single liquidation can close
+        // the account.
+        let plan = plan_for_account(&[900], &[420, 420], u64::MAX, DEFAULT_FULL_LIQUIDATION_HEALTH_FACTOR).unwrap();
+        assert_eq!(plan.repay_amount, 420);
+        assert_eq!(plan.seizures[0].amount, 462);
+        assert!(plan.health_after.health_factor < plan.health_before.health_factor);
+        assert_eq!(plan.health_after.debt_value, 420 * PRICE_PRECISION as u128);
+    }
+
+    #[test]
+    fn test_emptying_one_of_two_collaterals_is_allowed() {
+        // Health factor 800 / 950 = 0.84: seizing all of the smaller
+        // collateral lowers it but leaves the other one and part of the debt.
+        let plan = plan_for_account(&[200, 800], &[950], u64::MAX, DEFAULT_FULL_LIQUIDATION_HEALTH_FACTOR).unwrap();
+        assert_eq!(plan.seizures[0].amount, 200);
+        assert_eq!(plan.repay_amount, 182);
+        assert!(plan.repay_amount < plan.owed);
+        assert!(plan.health_after.health_factor < plan.health_before.health_factor);
+    }
+
+    #[test]
+    fn test_liquidation_makes_progress() {
+        let mint = Pubkey::new_unique();
+        let mut plan = LiquidationPlan {
+            borrow_mint: Pubkey::new_unique(),
+            owed: 100,
+            repay_amount: 50,
+            seizures: vec![Seizure { mint, amount: 1, protocol_fee: 0 }],
+            bonus_value: 0,
+            health_before: HealthReport { collateral_value: 100, debt_value: 100, health_factor: 800, ..Default::default() },
+            health_after: HealthReport { collateral_value: 45, debt_value: 50, health_factor: 700, ..Default::default() },
+        };
+        let mut after = UserDebtAccount {
+            owner: Pubkey::default(),
+            collateral_balances: vec![CollateralBalance { mint, amount: 1 }],
+            debt_balances: Vec::new(),
+            accumulated_points: 0,
+            last_points_update_ts: 0,
+            referrer: None,
+            bump: 0,
+            cached_health: HealthReport::default(),
+            health_refresh_slot: 0,
+            unhealthy_since_ts: 0,
+            delegate: None,
+        };
+        assert!(!liquidation_makes_progress(&plan, &after));
+
+        after.collateral_balances[0].amount = 0;
+        assert!(liquidation_makes_progress(&plan, &after));
+        after.collateral_balances[0].amount = 1;
+
+        plan.repay_amount = 100;
+        assert!(liquidation_makes_progress(&plan, &after));
+        plan.repay_amount = 50;
+
+        // Underwater by 10 before and 5 after.
+        plan.health_before.collateral_value = 90;
+        assert!(liquidation_makes_progress(&plan, &after));
+        plan.health_before.collateral_value = 100;
+
+        plan.health_after.health_factor = 800;
+        assert!(liquidation_makes_progress(&plan, &after));
+    }
 }
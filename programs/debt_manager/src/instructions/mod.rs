@@ -1,11 +1,17 @@
 pub mod admin;
+pub mod insurance;
 pub mod user;
 pub mod liquidation;
 pub mod flashloan;
 pub mod swap;
+pub mod leverage;
+pub mod query;
 
 pub use admin::*;
+pub use insurance::*;
 pub use user::*;
 pub use liquidation::*;
 pub use flashloan::*;
 pub use swap::*;
+pub use leverage::*;
+pub use query::*;
@@ -0,0 +1,75 @@
+use anchor_lang::prelude::*;
+use crate::state::*;
+use crate::health::{self, AccountHealth, HealthReport};
+use crate::instructions::liquidation;
+
+#[derive(Accounts)]
+pub struct GetAccountHealth<'info> {
+    #[account(seeds = [b"debt", user.key().as_ref()], bump = debt_account.bump)]
+    pub debt_account: Account<'info, UserDebtAccount>,
+    /// CHECK: Used only as a seed for deriving the debt_account PDA
+    pub user: AccountInfo<'info>,
+    #[account(seeds = [b"config"], bump = config.bump)]
+    pub config: Account<'info, ProtocolConfig>,
+}
+
+/// Read-only. Price feeds for every position, plus any borrow asset whose
+/// capacity should be reported, are passed in `remaining_accounts`. The
+/// result is returned to the caller as borsh-encoded return data.
+pub fn get_account_health<'info>(
+    ctx: Context<'_, '_, 'info, 'info, GetAccountHealth<'info>>,
+) -> Result<AccountHealth> {
+    let now = Clock::get()?.unix_timestamp;
+    health::compute_account_health(&ctx.accounts.config, &ctx.accounts.debt_account, ctx.remaining_accounts, now)
+}
+
+/// Return data of `preview_liquidation`.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug)]
+pub struct LiquidationPreview {
+    /// Debt actually repaid after the close factor and collateral caps.
+    pub repay_amount: u64,
+    pub seized_amount: u64,
+    /// Part of `seized_amount` paid to the treasury.
+    pub protocol_fee: u64,
+    /// Value seized above the repaid value, in `PRICE_PRECISION` units.
+    pub bonus_value: u128,
+    pub health_after: HealthReport,
+}
+
+#[derive(Accounts)]
+#[instruction(user: Pubkey)]
+pub struct PreviewLiquidation<'info> {
+    #[account(seeds = [b"debt", user.as_ref()], bump = debt_account.bump)]
+    pub debt_account: Account<'info, UserDebtAccount>,
+    #[account(seeds = [b"config"], bump = config.bump)]
+    pub config: Account<'info, ProtocolConfig>,
+}
+
+/// Read-only. Runs the same plan `liquidate` would execute right now, with
+/// the price feeds of every position in `remaining_accounts`.
+pub fn preview_liquidation<'info>(
+    ctx: Context<'_, '_, 'info, 'info, PreviewLiquidation<'info>>,
+    borrow_mint: Pubkey,
+    collateral_mint: Pubkey,
+    amount: u64,
+) -> Result<LiquidationPreview> {
+    let now = Clock::get()?.unix_timestamp;
+    let collaterals = [CollateralToSeize { mint: collateral_mint, max_amount: u64::MAX }];
+    let plan = liquidation::plan_liquidation(
+        &ctx.accounts.config,
+        &ctx.accounts.debt_account,
+        borrow_mint,
+        &collaterals,
+        amount,
+        ctx.remaining_accounts,
+        now,
+    )?;
+
+    Ok(LiquidationPreview {
+        repay_amount: plan.repay_amount,
+        seized_amount: plan.seizures[0].amount,
+        protocol_fee: plan.seizures[0].protocol_fee,
+        bonus_value: plan.bonus_value,
+        health_after: plan.health_after,
+    })
+}
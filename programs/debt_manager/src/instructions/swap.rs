@@ -1,24 +1,237 @@
 use anchor_lang::prelude::*;
+use anchor_lang::solana_program::entrypoint::ProgramResult;
 use anchor_lang::solana_program::instruction::Instruction;
 use anchor_lang::solana_program::program::invoke;
+use anchor_spl::token::TokenAccount;
+use crate::errors::ErrorCode;
+use crate::state::ProtocolConfig;
+
+/// Most swap router programs that can be allowed at once.
+pub const MAX_SWAP_PROGRAMS: usize = 4;
+
+/// Anchor discriminator of `swap`, the instruction name used by both the
+/// constant-product and Whirlpool interfaces.
+const SWAP_DISCRIMINATOR: [u8; 8] = [248, 198, 158, 145, 225, 117, 135, 200];
+/// Whirlpool's sqrt price bounds, used as "no limit" for exact-in swaps.
+pub const WHIRLPOOL_MIN_SQRT_PRICE_X64: u128 = 4_295_048_016;
+pub const WHIRLPOOL_MAX_SQRT_PRICE_X64: u128 = 79_226_673_515_401_279_992_447_579_055;
+
+/// Performs the CPI into a swap venue: `invoke` on-chain, or a simulation of
+/// the venue in tests.
+pub type SwapInvoke<'info> = fn(&Instruction, &[AccountInfo<'info>]) -> ProgramResult;
+
+/// Builds the swap instruction data of one venue. The venue's accounts are
+/// passed through unchanged, in its own order.
+pub trait SwapAdapter {
+    /// Data swapping exactly `amount_in` for at least `min_amount_out`.
+    fn instruction_data(&self, amount_in: u64, min_amount_out: u64) -> Result<Vec<u8>>;
+}
+
+/// Jupiter routes are quoted off-chain and carry their own amounts, so the
+/// route data is used as-is and the limits are only enforced on balances.
+pub struct JupiterAdapter<'a> {
+    pub route_data: &'a [u8],
+}
+
+impl SwapAdapter for JupiterAdapter<'_> {
+    fn instruction_data(&self, _amount_in: u64, _min_amount_out: u64) -> Result<Vec<u8>> {
+        Ok(self.route_data.to_vec())
+    }
+}
+
+/// Constant-product pool exposing `swap(amount_in, minimum_amount_out)`, as
+/// `mock_amm` does.
+pub struct ConstantProductAdapter;
+
+impl SwapAdapter for ConstantProductAdapter {
+    fn instruction_data(&self, amount_in: u64, min_amount_out: u64) -> Result<Vec<u8>> {
+        let mut data = SWAP_DISCRIMINATOR.to_vec();
+        (amount_in, min_amount_out).serialize(&mut data)?;
+        Ok(data)
+    }
+}
+
+/// Orca Whirlpool-style concentrated liquidity pool.
+pub struct WhirlpoolAdapter {
+    pub a_to_b: bool,
+}
+
+impl SwapAdapter for WhirlpoolAdapter {
+    fn instruction_data(&self, amount_in: u64, min_amount_out: u64) -> Result<Vec<u8>> {
+        let sqrt_price_limit = if self.a_to_b {
+            WHIRLPOOL_MIN_SQRT_PRICE_X64
+        } else {
+            WHIRLPOOL_MAX_SQRT_PRICE_X64
+        };
+        let mut data = SWAP_DISCRIMINATOR.to_vec();
+        (amount_in, min_amount_out, sqrt_price_limit, true, self.a_to_b).serialize(&mut data)?;
+        Ok(data)
+    }
+}
+
+/// Venue a swap is routed through, chosen per call.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug, PartialEq, Eq)]
+pub enum SwapRoute {
+    Jupiter { data: Vec<u8> },
+    ConstantProduct,
+    Whirlpool { a_to_b: bool },
+}
+
+impl SwapRoute {
+    pub fn adapter(&self) -> Box<dyn SwapAdapter + '_> {
+        match self {
+            SwapRoute::Jupiter { data } => Box::new(JupiterAdapter { route_data: data }),
+            SwapRoute::ConstantProduct => Box::new(ConstantProductAdapter),
+            SwapRoute::Whirlpool { a_to_b } => Box::new(WhirlpoolAdapter { a_to_b: *a_to_b }),
+        }
+    }
+}
+
+/// Return data of `jupiter_swap` and `route_swap`: what actually left the source account and
+/// arrived in the destination account.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SwapResult {
+    pub amount_in: u64,
+    pub amount_out: u64,
+}
 
 #[derive(Accounts)]
 pub struct JupiterSwapCpi<'info> {
-    /// CHECK: The Jupiter Swap Program
+    /// CHECK: The Jupiter Swap Program, checked against `config.swap_programs`
     pub jupiter_program: AccountInfo<'info>,
     /// CHECK: The authority executing the swap (could be a user or a PDA)
     #[account(signer)]
     pub authority: AccountInfo<'info>,
     /// CHECK: The Token Program
     pub token_program: AccountInfo<'info>,
+    #[account(seeds = [b"config"], bump = config.bump)]
+    pub config: Account<'info, ProtocolConfig>,
+    #[account(constraint = source_token_account.owner == authority.key() @ ErrorCode::Unauthorized)]
+    pub source_token_account: Account<'info, TokenAccount>,
+    #[account(
+        constraint = destination_token_account.owner == authority.key() @ ErrorCode::Unauthorized,
+        constraint = destination_token_account.key() != source_token_account.key() @ ErrorCode::InvalidParameter
+    )]
+    pub destination_token_account: Account<'info, TokenAccount>,
 }
 
-pub fn jupiter_swap(ctx: Context<JupiterSwapCpi>, data: Vec<u8>) -> Result<()> {
-    // This is a simple passthrough that invokes the Jupiter program.
-    // In a production environment, you might want to validate input accounts or data.
-    
+/// Routes a swap through an allowed router, passing `remaining_accounts` and
+/// `data` through. The source and destination balances are compared before
+/// and after, so at most `max_amount_in` may be spent and at least
+/// `min_amount_out` must be received.
+pub fn jupiter_swap<'info>(
+    ctx: Context<'_, '_, 'info, 'info, JupiterSwapCpi<'info>>,
+    data: Vec<u8>,
+    min_amount_out: u64,
+    max_amount_in: u64,
+) -> Result<SwapResult> {
+    let accounts = ctx.accounts;
+    swap_with_limits(
+        &accounts.config,
+        &accounts.jupiter_program,
+        ctx.remaining_accounts,
+        JupiterAdapter { route_data: &data }.instruction_data(0, min_amount_out)?,
+        &mut accounts.source_token_account,
+        &mut accounts.destination_token_account,
+        min_amount_out,
+        max_amount_in,
+        invoke,
+    )
+}
+
+#[derive(Accounts)]
+pub struct RouteSwap<'info> {
+    /// CHECK: The venue's program, checked against `config.swap_programs`
+    pub swap_program: AccountInfo<'info>,
+    /// CHECK: The authority executing the swap (could be a user or a PDA)
+    #[account(signer)]
+    pub authority: AccountInfo<'info>,
+    /// CHECK: The Token Program
+    pub token_program: AccountInfo<'info>,
+    #[account(seeds = [b"config"], bump = config.bump)]
+    pub config: Account<'info, ProtocolConfig>,
+    #[account(constraint = source_token_account.owner == authority.key() @ ErrorCode::Unauthorized)]
+    pub source_token_account: Account<'info, TokenAccount>,
+    #[account(
+        constraint = destination_token_account.owner == authority.key() @ ErrorCode::Unauthorized,
+        constraint = destination_token_account.key() != source_token_account.key() @ ErrorCode::InvalidParameter
+    )]
+    pub destination_token_account: Account<'info, TokenAccount>,
+}
+
+/// Swaps exactly `amount_in` through the venue selected by `route`, whose
+/// accounts are the `remaining_accounts`.
+pub fn route_swap<'info>(
+    ctx: Context<'_, '_, 'info, 'info, RouteSwap<'info>>,
+    route: SwapRoute,
+    amount_in: u64,
+    min_amount_out: u64,
+) -> Result<SwapResult> {
+    execute_route_swap(ctx.accounts, ctx.remaining_accounts, &route, amount_in, min_amount_out, invoke)
+}
+
+/// Body of `route_swap`, with the venue CPI made through `invoke_swap`.
+pub fn execute_route_swap<'info>(
+    accounts: &mut RouteSwap<'info>,
+    venue_accounts: &[AccountInfo<'info>],
+    route: &SwapRoute,
+    amount_in: u64,
+    min_amount_out: u64,
+    invoke_swap: SwapInvoke<'info>,
+) -> Result<SwapResult> {
+    swap_with_limits(
+        &accounts.config,
+        &accounts.swap_program,
+        venue_accounts,
+        route.adapter().instruction_data(amount_in, min_amount_out)?,
+        &mut accounts.source_token_account,
+        &mut accounts.destination_token_account,
+        min_amount_out,
+        amount_in,
+        invoke_swap,
+    )
+}
+
+/// Swaps through `program` via `invoke_swap`, failing unless the balance
+/// changes of `source` and `destination` are within the limits.
+#[allow(clippy::too_many_arguments)]
+pub fn swap_with_limits<'info>(
+    config: &ProtocolConfig,
+    program: &AccountInfo<'info>,
+    accounts: &[AccountInfo<'info>],
+    data: Vec<u8>,
+    source: &mut Account<'info, TokenAccount>,
+    destination: &mut Account<'info, TokenAccount>,
+    min_amount_out: u64,
+    max_amount_in: u64,
+    invoke_swap: SwapInvoke<'info>,
+) -> Result<SwapResult> {
+    let instruction = swap_instruction(config, program, accounts, data)?;
+
+    let source_before = source.amount;
+    let destination_before = destination.amount;
+
+    invoke_swap(&instruction, accounts)?;
+
+    source.reload()?;
+    destination.reload()?;
+    swap_result(source_before, source.amount, destination_before, destination.amount, min_amount_out, max_amount_in)
+}
+
+/// The CPI into the venue `program`, passing `accounts` through in order
+/// with their signer and writable flags. Fails unless `program` is allowed.
+pub fn swap_instruction(
+    config: &ProtocolConfig,
+    program: &AccountInfo,
+    accounts: &[AccountInfo],
+    data: Vec<u8>,
+) -> Result<Instruction> {
+    if !config.swap_programs.contains(program.key) {
+        return Err(ErrorCode::SwapProgramNotAllowed.into());
+    }
+
     let mut account_metas = Vec::new();
-    for acc in ctx.remaining_accounts.iter() {
+    for acc in accounts.iter() {
         account_metas.push(if acc.is_writable {
             AccountMeta::new(acc.key(), acc.is_signer)
         } else {
@@ -26,16 +239,60 @@ pub fn jupiter_swap(ctx: Context<JupiterSwapCpi>, data: Vec<u8>) -> Result<()> {
         });
     }
 
-    let instruction = Instruction {
-        program_id: ctx.accounts.jupiter_program.key(),
+    Ok(Instruction {
+        program_id: program.key(),
         accounts: account_metas,
         data,
-    };
+    })
+}
+
+/// What a swap took from the source and paid into the destination, given
+/// both balances before and after it. Fails if more than `max_amount_in`
+/// was spent or less than `min_amount_out` received.
+pub fn swap_result(
+    source_before: u64,
+    source_after: u64,
+    destination_before: u64,
+    destination_after: u64,
+    min_amount_out: u64,
+    max_amount_in: u64,
+) -> Result<SwapResult> {
+    let amount_in = source_before.saturating_sub(source_after);
+    let amount_out = destination_after.saturating_sub(destination_before);
+    if amount_in > max_amount_in || amount_out < min_amount_out {
+        return Err(ErrorCode::SlippageExceeded.into());
+    }
+
+    Ok(SwapResult { amount_in, amount_out })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_constant_product_instruction_data() {
+        let data = SwapRoute::ConstantProduct.adapter().instruction_data(100, 90).unwrap();
+        assert_eq!(data[..8], SWAP_DISCRIMINATOR);
+        assert_eq!(data[8..16], 100u64.to_le_bytes());
+        assert_eq!(data[16..], 90u64.to_le_bytes());
+    }
 
-    invoke(
-        &instruction,
-        &ctx.remaining_accounts,
-    )?;
+    #[test]
+    fn test_whirlpool_instruction_data() {
+        let data = SwapRoute::Whirlpool { a_to_b: true }.adapter().instruction_data(100, 90).unwrap();
+        assert_eq!(data.len(), 8 + 8 + 8 + 16 + 1 + 1);
+        assert_eq!(data[24..40], WHIRLPOOL_MIN_SQRT_PRICE_X64.to_le_bytes());
+        assert_eq!(data[40..], [1, 1]);
 
-    Ok(())
+        let data = SwapRoute::Whirlpool { a_to_b: false }.adapter().instruction_data(100, 90).unwrap();
+        assert_eq!(data[24..40], WHIRLPOOL_MAX_SQRT_PRICE_X64.to_le_bytes());
+        assert_eq!(data[40..], [1, 0]);
+    }
+
+    #[test]
+    fn test_jupiter_route_passes_through() {
+        let route = SwapRoute::Jupiter { data: vec![1, 2, 3] };
+        assert_eq!(route.adapter().instruction_data(100, 90).unwrap(), vec![1, 2, 3]);
+    }
 }
@@ -4,13 +4,18 @@ use crate::state::*;
 use crate::errors::ErrorCode;
 use crate::math::*;
 use crate::points;
+use crate::health::{self, HealthReport};
+use crate::instructions::flashloan;
+
+/// Allocated size of a `UserDebtAccount`.
+pub const DEBT_ACCOUNT_SPACE: usize = 8 + 32 + 4 + (32 + 8) * 10 + 4 + (32 + 8 + 16) * 10 + 16 + 8 + 33 + 1 + 16 * 5 + 8 + 8 + 33;
 
 #[derive(Accounts)]
 pub struct OpenDebtAccount<'info> {
     #[account(
         init,
         payer = owner,
-        space = 8 + 32 + 4 + (32 + 8) * 10 + 4 + (32 + 8 + 16) * 10 + 1,
+        space = DEBT_ACCOUNT_SPACE,
         seeds = [b"debt", owner.key().as_ref()],
         bump
     )]
@@ -29,6 +34,102 @@ pub fn open_debt_account(ctx: Context<OpenDebtAccount>, referrer: Option<Pubkey>
     debt_account.last_points_update_ts = Clock::get()?.unix_timestamp;
     debt_account.referrer = referrer;
     debt_account.bump = ctx.bumps.debt_account;
+    debt_account.cached_health = HealthReport::default();
+    debt_account.health_refresh_slot = 0;
+    debt_account.unhealthy_since_ts = 0;
+    debt_account.delegate = None;
+    Ok(())
+}
+
+#[derive(Accounts)]
+pub struct SetDelegate<'info> {
+    #[account(mut, seeds = [b"debt", owner.key().as_ref()], bump = debt_account.bump)]
+    pub debt_account: Account<'info, UserDebtAccount>,
+    pub owner: Signer<'info>,
+}
+
+pub fn set_delegate(ctx: Context<SetDelegate>, delegate: Option<Pubkey>) -> Result<()> {
+    ctx.accounts.debt_account.delegate = delegate;
+    Ok(())
+}
+
+/// `UserDebtAccount` as laid out before the health cache, liquidation bonus
+/// ramp and delegate fields were added. Accounts in this layout are smaller
+/// than `DEBT_ACCOUNT_SPACE` and must go through `migrate_debt_account`.
+#[derive(AnchorSerialize, AnchorDeserialize)]
+struct LegacyUserDebtAccount {
+    owner: Pubkey,
+    collateral_balances: Vec<CollateralBalance>,
+    debt_balances: Vec<DebtBalance>,
+    accumulated_points: u128,
+    last_points_update_ts: i64,
+    referrer: Option<Pubkey>,
+    bump: u8,
+}
+
+#[derive(Accounts)]
+pub struct MigrateDebtAccount<'info> {
+    /// CHECK: Still in the legacy layout, so it cannot be deserialized as a
+    /// `UserDebtAccount`; owner and discriminator are checked in instruction
+    #[account(mut, seeds = [b"debt", user.key().as_ref()], bump)]
+    pub debt_account: UncheckedAccount<'info>,
+    /// CHECK: Used only as a seed for deriving the debt_account PDA
+    pub user: AccountInfo<'info>,
+    /// Pays the rent for the larger account.
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+/// Permissionless. Grows a debt account created before the layout change to
+/// `DEBT_ACCOUNT_SPACE` and rewrites it with the new fields at their
+/// defaults. Bytes past the legacy data are not trusted to be zero (balances
+/// may have shrunk), so the account is re-serialized rather than extended.
+pub fn migrate_debt_account(ctx: Context<MigrateDebtAccount>) -> Result<()> {
+    let info = ctx.accounts.debt_account.to_account_info();
+    if info.owner != &crate::ID {
+        return Err(anchor_lang::error::ErrorCode::AccountOwnedByWrongProgram.into());
+    }
+    if info.data_len() >= DEBT_ACCOUNT_SPACE {
+        return Err(ErrorCode::AccountAlreadyMigrated.into());
+    }
+    let legacy = {
+        let data = info.try_borrow_data()?;
+        if data.get(..8) != Some(UserDebtAccount::DISCRIMINATOR) {
+            return Err(anchor_lang::error::ErrorCode::AccountDiscriminatorMismatch.into());
+        }
+        LegacyUserDebtAccount::deserialize(&mut &data[8..])?
+    };
+
+    let rent = Rent::get()?.minimum_balance(DEBT_ACCOUNT_SPACE);
+    let shortfall = rent.saturating_sub(info.lamports());
+    if shortfall > 0 {
+        let cpi_accounts = anchor_lang::system_program::Transfer {
+            from: ctx.accounts.payer.to_account_info(),
+            to: info.clone(),
+        };
+        let cpi_ctx = CpiContext::new(ctx.accounts.system_program.to_account_info(), cpi_accounts);
+        anchor_lang::system_program::transfer(cpi_ctx, shortfall)?;
+    }
+    info.resize(DEBT_ACCOUNT_SPACE)?;
+
+    let account = UserDebtAccount {
+        owner: legacy.owner,
+        collateral_balances: legacy.collateral_balances,
+        debt_balances: legacy.debt_balances,
+        accumulated_points: legacy.accumulated_points,
+        last_points_update_ts: legacy.last_points_update_ts,
+        referrer: legacy.referrer,
+        bump: legacy.bump,
+        cached_health: HealthReport::default(),
+        health_refresh_slot: 0,
+        unhealthy_since_ts: 0,
+        delegate: None,
+    };
+    let mut data = info.try_borrow_mut_data()?;
+    data.fill(0);
+    let mut writer: &mut [u8] = &mut data;
+    account.try_serialize(&mut writer)?;
     Ok(())
 }
 
@@ -50,16 +151,20 @@ pub struct DepositCollateral<'info> {
     pub token_program: Program<'info, Token>,
     #[account(seeds = [b"config"], bump = config.bump)]
     pub config: Account<'info, ProtocolConfig>,
-    /// CHECK: Verified in instruction
-    pub price_feed: AccountInfo<'info>,
+    /// CHECK: Optional. Deposits only improve health, so no price is read; if
+    /// supplied it must still match the configured feed.
+    pub price_feed: Option<AccountInfo<'info>>,
 }
 
-pub fn deposit_collateral(ctx: Context<DepositCollateral>, amount: u64) -> Result<()> {
+pub fn deposit_collateral<'info>(ctx: Context<'_, '_, 'info, 'info, DepositCollateral<'info>>, amount: u64) -> Result<()> {
     let config = &ctx.accounts.config;
+    flashloan::check_vault_unlocked(config, &ctx.accounts.vault.key())?;
     let collateral_info = config.supported_collaterals.iter().find(|c| c.mint == ctx.accounts.collateral_mint.key()).ok_or(ErrorCode::UnsupportedCollateral)?;
     
-    if collateral_info.price_feed != ctx.accounts.price_feed.key() {
-        return Err(ErrorCode::InvalidPriceFeed.into());
+    if let Some(price_feed) = &ctx.accounts.price_feed {
+        if collateral_info.price_feed != price_feed.key() {
+            return Err(ErrorCode::InvalidPriceFeed.into());
+        }
     }
 
     let debt_account = &mut ctx.accounts.debt_account;
@@ -75,16 +180,53 @@ pub fn deposit_collateral(ctx: Context<DepositCollateral>, amount: u64) -> Resul
     let cpi_ctx = CpiContext::new(cpi_program, cpi_accounts);
     token::transfer(cpi_ctx, amount)?;
 
-    let debt_account = &mut ctx.accounts.debt_account;
-    if let Some(balance) = debt_account.collateral_balances.iter_mut().find(|b| b.mint == ctx.accounts.collateral_mint.key()) {
+    record_deposit(&mut ctx.accounts.debt_account, ctx.accounts.collateral_mint.key(), amount)?;
+    refresh_unhealthy_since(
+        &ctx.accounts.config,
+        &mut ctx.accounts.debt_account,
+        &ctx.accounts.price_feed,
+        ctx.remaining_accounts,
+        now,
+    )
+}
+
+/// Lets a deposit or repay end the liquidation bonus ramp of an account it
+/// brings back to health, so a later relapse starts the ramp afresh. With no
+/// debt left the account is healthy outright; otherwise health is only
+/// computed when price feeds for every position are passed in
+/// `remaining_accounts`. This is best-effort: if those feeds cannot be read
+/// the timestamp is left alone rather than failing the deposit or repay.
+fn refresh_unhealthy_since<'info>(
+    config: &ProtocolConfig,
+    debt_account: &mut UserDebtAccount,
+    price_feed: &Option<AccountInfo<'info>>,
+    remaining_accounts: &[AccountInfo<'info>],
+    now: i64,
+) -> Result<()> {
+    if debt_account.debt_balances.iter().all(|d| d.principal == 0) {
+        debt_account.unhealthy_since_ts = 0;
+        return Ok(());
+    }
+    if remaining_accounts.is_empty() {
+        return Ok(());
+    }
+    let mut feeds: Vec<AccountInfo<'info>> = price_feed.iter().cloned().collect();
+    feeds.extend_from_slice(remaining_accounts);
+    let report = match health::compute_health(config, debt_account, &feeds, now) {
+        Ok(report) => report,
+        Err(_) => return Ok(()),
+    };
+    health::store_cached_health(debt_account, report, Clock::get()?.slot);
+    health::update_unhealthy_since(debt_account, &report, now);
+    Ok(())
+}
+
+pub fn record_deposit(debt_account: &mut UserDebtAccount, mint: Pubkey, amount: u64) -> Result<()> {
+    if let Some(balance) = debt_account.collateral_balances.iter_mut().find(|b| b.mint == mint) {
         balance.amount = balance.amount.checked_add(amount).ok_or(ErrorCode::MathOverflow)?;
     } else {
-        debt_account.collateral_balances.push(CollateralBalance {
-            mint: ctx.accounts.collateral_mint.key(),
-            amount,
-        });
+        debt_account.collateral_balances.push(CollateralBalance { mint, amount });
     }
-
     Ok(())
 }
 
@@ -110,8 +252,9 @@ pub struct WithdrawCollateral<'info> {
     pub price_feed: AccountInfo<'info>,
 }
 
-pub fn withdraw_collateral(ctx: Context<WithdrawCollateral>, amount: u64) -> Result<()> {
+pub fn withdraw_collateral<'info>(ctx: Context<'_, '_, 'info, 'info, WithdrawCollateral<'info>>, amount: u64) -> Result<()> {
     let config = &ctx.accounts.config;
+    flashloan::check_vault_unlocked(config, &ctx.accounts.vault.key())?;
     let collateral_info = config.supported_collaterals.iter().find(|c| c.mint == ctx.accounts.collateral_mint.key()).ok_or(ErrorCode::UnsupportedCollateral)?;
     
     if collateral_info.price_feed != ctx.accounts.price_feed.key() {
@@ -133,46 +276,24 @@ pub fn withdraw_collateral(ctx: Context<WithdrawCollateral>, amount: u64) -> Res
         return Err(ErrorCode::InsufficientCollateral.into());
     }
 
-    if !debt_account.debt_balances.is_empty() {
-        let now = Clock::get()?.unix_timestamp as u64;
-        
-        let mut total_collateral_value_with_ltv: u128 = 0;
-        for c in &debt_account.collateral_balances {
-            if let Some(info) = config.supported_collaterals.iter().find(|x| x.mint == c.mint) {
-                let price = get_price_from_feed(
-                    ctx.remaining_accounts.iter().find(|acc| acc.key() == info.price_feed)
-                        .ok_or(ErrorCode::InvalidPriceFeed)?,
-                    60,
-                    now as i64
-                )?;
-                let value = (c.amount as u128) * (price as u128);
-                let value_with_ltv = value * (info.ltv as u128) / 10000;
-                total_collateral_value_with_ltv = total_collateral_value_with_ltv
-                    .checked_add(value_with_ltv)
-                    .ok_or(ErrorCode::MathOverflow)?;
-            }
-        }
-
-
-        let mut total_debt_value: u128 = 0;
-        for d in &debt_account.debt_balances {
-            if let Some(info) = config.supported_borrows.iter().find(|x| x.mint == d.borrow_mint) {
-                let price = get_price_from_feed(
-                    ctx.remaining_accounts.iter().find(|acc| acc.key() == info.price_feed)
-                        .ok_or(ErrorCode::InvalidPriceFeed)?,
-                    60,
-                    now as i64
-                )?;
-                let owed = calculate_owed_amount(d.principal, d.interest_index_snapshot, info.global_index)?;
-                let value = (owed as u128) * (price as u128);
-                total_debt_value = total_debt_value.checked_add(value).ok_or(ErrorCode::MathOverflow)?;
-            }
+    let slot = Clock::get()?.slot;
+    if let Some(mut report) = health::cached_health(debt_account, slot) {
+        let price = get_price_from_feed(&ctx.accounts.price_feed, health::PRICE_MAX_AGE, now)?;
+        report.remove_collateral(collateral_info, amount, price)?;
+        if !report.is_within_borrow_limit() {
+            return Err(ErrorCode::LTVExceeded.into());
         }
-
-        if total_debt_value > total_collateral_value_with_ltv {
-
+        health::store_cached_health(debt_account, report, slot);
+        health::update_unhealthy_since(debt_account, &report, now);
+    } else if !debt_account.debt_balances.is_empty() {
+        let mut feeds = vec![ctx.accounts.price_feed.clone()];
+        feeds.extend_from_slice(ctx.remaining_accounts);
+        let report = health::compute_health(config, debt_account, &feeds, now)?;
+        if !report.is_within_borrow_limit() {
             return Err(ErrorCode::LTVExceeded.into());
         }
+        health::store_cached_health(debt_account, report, slot);
+        health::update_unhealthy_since(debt_account, &report, now);
     }
 
     let mint_key = ctx.accounts.collateral_mint.key();
@@ -214,7 +335,7 @@ pub struct Borrow<'info> {
     pub price_feed: AccountInfo<'info>,
 }
 
-pub fn borrow(ctx: Context<Borrow>, amount: u64) -> Result<()> {
+pub fn borrow<'info>(ctx: Context<'_, '_, 'info, 'info, Borrow<'info>>, amount: u64) -> Result<()> {
     let config = &mut ctx.accounts.config;
     let now = Clock::get()?.unix_timestamp as u64;
     let borrow_mint_key = ctx.accounts.borrow_mint.key();
@@ -228,82 +349,26 @@ pub fn borrow(ctx: Context<Borrow>, amount: u64) -> Result<()> {
     
     let debt_account = &mut ctx.accounts.debt_account;
     points::update_points(debt_account, now as i64)?;
-
-    let borrow_price = get_price_from_feed(&ctx.accounts.price_feed, 60, now as i64)?;
-    
-    let utilization = crate::math::calculate_utilization(asset.total_borrows, asset.total_deposits);
-    let borrow_rate = crate::math::calculate_borrow_rate(
-        utilization,
-        asset.base_rate,
-        asset.optimal_utilization,
-        asset.slope1,
-        asset.slope2
-    );
-    let annual_rate = crate::math::calculate_annual_rate_from_borrow_rate(borrow_rate);
-    
-    let new_index = update_global_index(asset.global_index, annual_rate, asset.last_update_ts, now)?;
-    asset.global_index = new_index;
-    asset.last_update_ts = now;
-    let current_global_index = asset.global_index;
-
-    asset.total_borrows = asset.total_borrows.checked_add(amount).ok_or(ErrorCode::MathOverflow)?;
-
-    let debt_account = &mut ctx.accounts.debt_account;
-    let debt_slot_index = debt_account.debt_balances.iter().position(|d| d.borrow_mint == borrow_mint_key);
-
-    if let Some(idx) = debt_slot_index {
-        let slot = &mut debt_account.debt_balances[idx];
-        let owed_now = calculate_owed_amount(slot.principal, slot.interest_index_snapshot, current_global_index)?;
-        let new_principal = owed_now.checked_add(amount).ok_or(ErrorCode::MathOverflow)?;
-        slot.principal = new_principal;
-        slot.interest_index_snapshot = current_global_index;
-    } else {
-        debt_account.debt_balances.push(DebtBalance {
-            borrow_mint: borrow_mint_key,
-            principal: amount,
-            interest_index_snapshot: current_global_index,
-        });
-    }
-
-    let mut total_collateral_value_with_ltv: u128 = 0;
-    for c in &debt_account.collateral_balances {
-        if let Some(info) = config.supported_collaterals.iter().find(|x| x.mint == c.mint) {
-            let price = get_price_from_feed(
-                ctx.remaining_accounts.iter().find(|acc| acc.key() == info.price_feed)
-                    .ok_or(ErrorCode::InvalidPriceFeed)?,
-                60,
-                now as i64
-            )?;
-            let value = (c.amount as u128) * (price as u128);
-            let value_with_ltv = value * (info.ltv as u128) / 10000;
-            total_collateral_value_with_ltv = total_collateral_value_with_ltv
-                .checked_add(value_with_ltv)
-                .ok_or(ErrorCode::MathOverflow)?;
+    record_borrow(asset, debt_account, amount, now)?;
+
+    let slot = Clock::get()?.slot;
+    let report = match health::cached_health(debt_account, slot) {
+        Some(mut report) => {
+            let price = get_price_from_feed(&ctx.accounts.price_feed, health::PRICE_MAX_AGE, now as i64)?;
+            report.add_debt(amount, price)?;
+            report
         }
-    }
-
-    let mut total_debt_value: u128 = 0;
-    for d in &debt_account.debt_balances {
-        if let Some(info) = config.supported_borrows.iter().find(|x| x.mint == d.borrow_mint) {
-            let price = if info.mint == borrow_mint_key {
-                borrow_price
-            } else {
-                get_price_from_feed(
-                    ctx.remaining_accounts.iter().find(|acc| acc.key() == info.price_feed)
-                        .ok_or(ErrorCode::InvalidPriceFeed)?,
-                    60,
-                    now as i64
-                )?
-            };
-            let owed = calculate_owed_amount(d.principal, d.interest_index_snapshot, info.global_index)?;
-            let value = (owed as u128) * (price as u128);
-            total_debt_value = total_debt_value.checked_add(value).ok_or(ErrorCode::MathOverflow)?;
+        None => {
+            let mut feeds = vec![ctx.accounts.price_feed.clone()];
+            feeds.extend_from_slice(ctx.remaining_accounts);
+            health::compute_health(config, debt_account, &feeds, now as i64)?
         }
-    }
-
-    if total_debt_value > total_collateral_value_with_ltv {
+    };
+    if !report.is_within_borrow_limit() {
         return Err(ErrorCode::LTVExceeded.into());
     }
+    health::store_cached_health(debt_account, report, slot);
+    health::update_unhealthy_since(debt_account, &report, now as i64);
 
     let bump = ctx.accounts.config.bump;
     let seeds = &[
@@ -325,6 +390,35 @@ pub fn borrow(ctx: Context<Borrow>, amount: u64) -> Result<()> {
 }
 
 
+/// Accrues `asset` up to `now` and adds `amount` to the account's debt in it.
+pub fn record_borrow(
+    asset: &mut BorrowAssetInfo,
+    debt_account: &mut UserDebtAccount,
+    amount: u64,
+    now: u64,
+) -> Result<()> {
+    let current_global_index = accrue_global_index(asset, now)?;
+
+    asset.total_borrows = asset.total_borrows.checked_add(amount).ok_or(ErrorCode::MathOverflow)?;
+
+    let debt_slot_index = debt_account.debt_balances.iter().position(|d| d.borrow_mint == asset.mint);
+
+    if let Some(idx) = debt_slot_index {
+        let slot = &mut debt_account.debt_balances[idx];
+        let owed_now = calculate_owed_amount(slot.principal, slot.interest_index_snapshot, current_global_index)?;
+        let new_principal = owed_now.checked_add(amount).ok_or(ErrorCode::MathOverflow)?;
+        slot.principal = new_principal;
+        slot.interest_index_snapshot = current_global_index;
+    } else {
+        debt_account.debt_balances.push(DebtBalance {
+            borrow_mint: asset.mint,
+            principal: amount,
+            interest_index_snapshot: current_global_index,
+        });
+    }
+    Ok(())
+}
+
 #[derive(Accounts)]
 pub struct Repay<'info> {
     #[account(mut, seeds = [b"debt", owner.key().as_ref()], bump = debt_account.bump)]
@@ -338,11 +432,12 @@ pub struct Repay<'info> {
     #[account(mut)]
     pub borrow_mint: Account<'info, Mint>,
     pub token_program: Program<'info, Token>,
-    /// CHECK: Verified in instruction
-    pub price_feed: AccountInfo<'info>,
+    /// CHECK: Optional. Repaying only improves health, so no price is read; if
+    /// supplied it must still match the configured feed.
+    pub price_feed: Option<AccountInfo<'info>>,
 }
 
-pub fn repay(ctx: Context<Repay>, amount: u64) -> Result<()> {
+pub fn repay<'info>(ctx: Context<'_, '_, 'info, 'info, Repay<'info>>, amount: u64) -> Result<()> {
     let config = &mut ctx.accounts.config;
     let now = Clock::get()?.unix_timestamp as u64;
     let borrow_mint_key = ctx.accounts.borrow_mint.key();
@@ -350,24 +445,13 @@ pub fn repay(ctx: Context<Repay>, amount: u64) -> Result<()> {
     let asset_index = config.supported_borrows.iter().position(|a| a.mint == borrow_mint_key).ok_or(ErrorCode::UnsupportedBorrowAsset)?;
     let asset = &mut config.supported_borrows[asset_index];
     
-    if asset.price_feed != ctx.accounts.price_feed.key() {
-        return Err(ErrorCode::InvalidPriceFeed.into());
+    if let Some(price_feed) = &ctx.accounts.price_feed {
+        if asset.price_feed != price_feed.key() {
+            return Err(ErrorCode::InvalidPriceFeed.into());
+        }
     }
     
-    let utilization = crate::math::calculate_utilization(asset.total_borrows, asset.total_deposits);
-    let borrow_rate = crate::math::calculate_borrow_rate(
-        utilization,
-        asset.base_rate,
-        asset.optimal_utilization,
-        asset.slope1,
-        asset.slope2
-    );
-    let annual_rate = crate::math::calculate_annual_rate_from_borrow_rate(borrow_rate);
-    
-    let new_index = update_global_index(asset.global_index, annual_rate, asset.last_update_ts, now)?;
-    asset.global_index = new_index;
-    asset.last_update_ts = now;
-    let current_global_index = asset.global_index;
+    let current_global_index = accrue_global_index(asset, now)?;
 
     let debt_account = &mut ctx.accounts.debt_account;
     points::update_points(debt_account, now as i64)?;
@@ -395,7 +479,13 @@ pub fn repay(ctx: Context<Repay>, amount: u64) -> Result<()> {
     let cpi_ctx = CpiContext::new(cpi_program, cpi_accounts);
     token::burn(cpi_ctx, repay_amount)?;
 
-    Ok(())
+    refresh_unhealthy_since(
+        &ctx.accounts.config,
+        &mut ctx.accounts.debt_account,
+        &ctx.accounts.price_feed,
+        ctx.remaining_accounts,
+        now as i64,
+    )
 }
 
 #[derive(Accounts)]
@@ -409,20 +499,29 @@ pub fn accrue_interest(ctx: Context<AccrueInterest>) -> Result<()> {
     let now = Clock::get()?.unix_timestamp as u64;
 
     for asset in config.supported_borrows.iter_mut() {
-        let utilization = crate::math::calculate_utilization(asset.total_borrows, asset.total_deposits);
-        let borrow_rate = crate::math::calculate_borrow_rate(
-            utilization,
-            asset.base_rate,
-            asset.optimal_utilization,
-            asset.slope1,
-            asset.slope2
-        );
-        let annual_rate = crate::math::calculate_annual_rate_from_borrow_rate(borrow_rate);
-        
-        let new_index = update_global_index(asset.global_index, annual_rate, asset.last_update_ts, now)?;
-        asset.global_index = new_index;
-        asset.last_update_ts = now;
+        accrue_global_index(asset, now)?;
     }
 
     Ok(())
 }
+
+#[derive(Accounts)]
+pub struct RefreshAccount<'info> {
+    #[account(mut, seeds = [b"debt", user.key().as_ref()], bump = debt_account.bump)]
+    pub debt_account: Account<'info, UserDebtAccount>,
+    /// CHECK: Used only as a seed for deriving the debt_account PDA
+    pub user: AccountInfo<'info>,
+    #[account(seeds = [b"config"], bump = config.bump)]
+    pub config: Account<'info, ProtocolConfig>,
+}
+
+/// Recomputes and caches the account's health so that borrow and withdraw
+/// later in the same slot only need the feed of the asset they touch.
+/// Price feeds for every position are passed in `remaining_accounts`.
+pub fn refresh_account<'info>(ctx: Context<'_, '_, 'info, 'info, RefreshAccount<'info>>) -> Result<()> {
+    let clock = Clock::get()?;
+    let report = health::compute_health(&ctx.accounts.config, &ctx.accounts.debt_account, ctx.remaining_accounts, clock.unix_timestamp)?;
+    health::store_cached_health(&mut ctx.accounts.debt_account, report, clock.slot);
+    health::update_unhealthy_since(&mut ctx.accounts.debt_account, &report, clock.unix_timestamp);
+    Ok(())
+}
@@ -5,8 +5,11 @@ pub mod instructions;
 pub mod math;
 pub mod errors;
 pub mod points;
+pub mod health;
 
 use instructions::*;
+use health::AccountHealth;
+use state::{CollateralToSeize, LiquidationParams, LiquidationRequest};
 
 declare_id!("Bym9jYYsJryghp9KWgsRAMT97NoRizbajvEjTKehZNwZ");
 
@@ -18,6 +21,10 @@ pub mod debt_manager {
         instructions::admin::initialize_protocol(ctx)
     }
 
+    pub fn migrate_config(ctx: Context<MigrateConfig>) -> Result<()> {
+        instructions::admin::migrate_config(ctx)
+    }
+
     pub fn add_supported_collateral(
         ctx: Context<AddSupportedCollateral>,
         mint: Pubkey,
@@ -56,40 +63,199 @@ pub mod debt_manager {
         instructions::admin::update_rate_model(ctx, mint, base_rate, optimal_utilization, slope1, slope2)
     }
 
+    pub fn initialize_insurance_fund(
+        ctx: Context<InitializeInsuranceFund>,
+        cooldown_seconds: u64,
+        unstake_window_seconds: u64,
+        max_slash_bps: u64,
+        fee_share_bps: u64,
+    ) -> Result<()> {
+        instructions::insurance::initialize_insurance_fund(ctx, cooldown_seconds, unstake_window_seconds, max_slash_bps, fee_share_bps)
+    }
+
+    pub fn open_insurance_stake(ctx: Context<OpenInsuranceStake>) -> Result<()> {
+        instructions::insurance::open_insurance_stake(ctx)
+    }
+
+    pub fn stake_insurance(ctx: Context<StakeInsurance>, amount: u64) -> Result<()> {
+        instructions::insurance::stake_insurance(ctx, amount)
+    }
+
+    pub fn request_insurance_unstake(ctx: Context<RequestInsuranceUnstake>) -> Result<()> {
+        instructions::insurance::request_insurance_unstake(ctx)
+    }
+
+    pub fn unstake_insurance(ctx: Context<UnstakeInsurance>, shares: u64) -> Result<()> {
+        instructions::insurance::unstake_insurance(ctx, shares)
+    }
+
+    pub fn update_flash_loan_fee(ctx: Context<UpdateFlashLoanConfig>, mint: Pubkey, fee_bps: u64) -> Result<()> {
+        instructions::admin::update_flash_loan_fee(ctx, mint, fee_bps)
+    }
+
+    pub fn update_flash_mint_ceiling(ctx: Context<UpdateFlashLoanConfig>, mint: Pubkey, ceiling: u64) -> Result<()> {
+        instructions::admin::update_flash_mint_ceiling(ctx, mint, ceiling)
+    }
+
+    pub fn set_fee_exempt_program(ctx: Context<UpdateFlashLoanConfig>, program: Pubkey, exempt: bool) -> Result<()> {
+        instructions::admin::set_fee_exempt_program(ctx, program, exempt)
+    }
+
+    pub fn set_swap_program(ctx: Context<SetSwapProgram>, program: Pubkey, allowed: bool) -> Result<()> {
+        instructions::admin::set_swap_program(ctx, program, allowed)
+    }
+
+    pub fn update_liquidation_params(ctx: Context<UpdateLiquidationParams>, params: LiquidationParams) -> Result<()> {
+        instructions::admin::update_liquidation_params(ctx, params)
+    }
+
     pub fn open_debt_account(ctx: Context<OpenDebtAccount>, referrer: Option<Pubkey>) -> Result<()> {
 
         instructions::user::open_debt_account(ctx, referrer)
     }
 
-    pub fn deposit_collateral(ctx: Context<DepositCollateral>, amount: u64) -> Result<()> {
+    pub fn set_delegate(ctx: Context<SetDelegate>, delegate: Option<Pubkey>) -> Result<()> {
+        instructions::user::set_delegate(ctx, delegate)
+    }
+
+    pub fn migrate_debt_account(ctx: Context<MigrateDebtAccount>) -> Result<()> {
+        instructions::user::migrate_debt_account(ctx)
+    }
+
+    pub fn deposit_collateral<'info>(ctx: Context<'_, '_, 'info, 'info, DepositCollateral<'info>>, amount: u64) -> Result<()> {
         instructions::user::deposit_collateral(ctx, amount)
     }
 
-    pub fn withdraw_collateral(ctx: Context<WithdrawCollateral>, amount: u64) -> Result<()> {
+    pub fn withdraw_collateral<'info>(ctx: Context<'_, '_, 'info, 'info, WithdrawCollateral<'info>>, amount: u64) -> Result<()> {
         instructions::user::withdraw_collateral(ctx, amount)
     }
 
-    pub fn borrow(ctx: Context<Borrow>, amount: u64) -> Result<()> {
+    pub fn borrow<'info>(ctx: Context<'_, '_, 'info, 'info, Borrow<'info>>, amount: u64) -> Result<()> {
         instructions::user::borrow(ctx, amount)
     }
 
-    pub fn repay(ctx: Context<Repay>, amount: u64) -> Result<()> {
+    pub fn repay<'info>(ctx: Context<'_, '_, 'info, 'info, Repay<'info>>, amount: u64) -> Result<()> {
         instructions::user::repay(ctx, amount)
     }
 
-    pub fn liquidate(ctx: Context<Liquidate>, amount: u64) -> Result<()> {
+    pub fn liquidate<'info>(ctx: Context<'_, '_, 'info, 'info, Liquidate<'info>>, amount: u64) -> Result<()> {
         instructions::liquidation::liquidate(ctx, amount)
     }
 
+    pub fn refresh_account<'info>(ctx: Context<'_, '_, 'info, 'info, RefreshAccount<'info>>) -> Result<()> {
+        instructions::user::refresh_account(ctx)
+    }
+
+    pub fn liquidate_multi<'info>(
+        ctx: Context<'_, '_, 'info, 'info, LiquidateMulti<'info>>,
+        amount: u64,
+        collaterals: Vec<CollateralToSeize>,
+    ) -> Result<()> {
+        instructions::liquidation::liquidate_multi(ctx, amount, collaterals)
+    }
+
+    pub fn liquidate_many<'info>(
+        ctx: Context<'_, '_, 'info, 'info, LiquidateMany<'info>>,
+        requests: Vec<LiquidationRequest>,
+    ) -> Result<Vec<LiquidationOutcome>> {
+        instructions::liquidation::liquidate_many(ctx, requests)
+    }
+
+    pub fn flash_liquidate<'info>(
+        ctx: Context<'_, '_, 'info, 'info, FlashLiquidate<'info>>,
+        amount: u64,
+        feed_count: u8,
+        instruction_data: Vec<u8>,
+    ) -> Result<()> {
+        instructions::liquidation::flash_liquidate(ctx, amount, feed_count, instruction_data)
+    }
+
+    pub fn mark_unhealthy<'info>(ctx: Context<'_, '_, 'info, 'info, MarkUnhealthy<'info>>) -> Result<()> {
+        instructions::liquidation::mark_unhealthy(ctx)
+    }
+
+    pub fn write_off_bad_debt(ctx: Context<WriteOffBadDebt>, borrow_mint: Pubkey) -> Result<()> {
+        instructions::liquidation::write_off_bad_debt(ctx, borrow_mint)
+    }
+
     pub fn accrue_interest(ctx: Context<AccrueInterest>) -> Result<()> {
         instructions::user::accrue_interest(ctx)
     }
 
-    pub fn flash_loan(ctx: Context<FlashLoanAction>, amount: u64, instruction_data: Vec<u8>) -> Result<()> {
-        instructions::flashloan::flash_loan(ctx, amount, instruction_data)
+    pub fn flash_loan<'info>(
+        ctx: Context<'_, '_, 'info, 'info, FlashLoanAction<'info>>,
+        amount: u64,
+        data: Vec<u8>,
+    ) -> Result<()> {
+        instructions::flashloan::flash_loan(ctx, amount, data)
+    }
+
+    pub fn flash_loan_multi<'info>(
+        ctx: Context<'_, '_, 'info, 'info, FlashLoanMulti<'info>>,
+        loans: Vec<FlashLoanRequest>,
+        data: Vec<u8>,
+    ) -> Result<()> {
+        instructions::flashloan::flash_loan_multi(ctx, loans, data)
+    }
+
+    pub fn flash_mint<'info>(
+        ctx: Context<'_, '_, 'info, 'info, FlashMint<'info>>,
+        amount: u64,
+        data: Vec<u8>,
+    ) -> Result<()> {
+        instructions::flashloan::flash_mint(ctx, amount, data)
+    }
+
+    pub fn flash_borrow(ctx: Context<FlashBorrow>, amount: u64) -> Result<()> {
+        instructions::flashloan::flash_borrow(ctx, amount)
+    }
+
+    pub fn flash_repay(ctx: Context<FlashRepay>, borrow_instruction_index: u8) -> Result<()> {
+        instructions::flashloan::flash_repay(ctx, borrow_instruction_index)
+    }
+
+    pub fn jupiter_swap<'info>(
+        ctx: Context<'_, '_, 'info, 'info, JupiterSwapCpi<'info>>,
+        data: Vec<u8>,
+        min_amount_out: u64,
+        max_amount_in: u64,
+    ) -> Result<SwapResult> {
+        instructions::swap::jupiter_swap(ctx, data, min_amount_out, max_amount_in)
+    }
+
+    pub fn route_swap<'info>(
+        ctx: Context<'_, '_, 'info, 'info, RouteSwap<'info>>,
+        route: SwapRoute,
+        amount_in: u64,
+        min_amount_out: u64,
+    ) -> Result<SwapResult> {
+        instructions::swap::route_swap(ctx, route, amount_in, min_amount_out)
+    }
+
+    pub fn open_leveraged_position<'info>(
+        ctx: Context<'_, '_, 'info, 'info, OpenLeveragedPosition<'info>>,
+        initial_amount: u64,
+        target_leverage: u64,
+        min_out: u64,
+        route: SwapRoute,
+        feed_count: u8,
+    ) -> Result<()> {
+        instructions::leverage::open_leveraged_position(ctx, initial_amount, target_leverage, min_out, route, feed_count)
+    }
+
+    pub fn get_account_health<'info>(
+        ctx: Context<'_, '_, 'info, 'info, GetAccountHealth<'info>>,
+    ) -> Result<AccountHealth> {
+        instructions::query::get_account_health(ctx)
     }
 
-    pub fn jupiter_swap(ctx: Context<JupiterSwapCpi>, data: Vec<u8>) -> Result<()> {
-        instructions::swap::jupiter_swap(ctx, data)
+    pub fn preview_liquidation<'info>(
+        ctx: Context<'_, '_, 'info, 'info, PreviewLiquidation<'info>>,
+        _user: Pubkey,
+        borrow_mint: Pubkey,
+        collateral_mint: Pubkey,
+        amount: u64,
+    ) -> Result<LiquidationPreview> {
+        instructions::query::preview_liquidation(ctx, borrow_mint, collateral_mint, amount)
     }
 }
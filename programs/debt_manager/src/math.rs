@@ -6,6 +6,7 @@ pub const SECONDS_PER_YEAR: u128 = 31_536_000;
 pub const PRICE_PRECISION: u64 = 1_000_000;
 
 use crate::errors::ErrorCode;
+use crate::state::BorrowAssetInfo;
 
 pub const UTILIZATION_PRECISION: u64 = 10000;
 pub const RATE_PRECISION: u64 = 10000;
@@ -104,6 +105,27 @@ pub fn update_global_index(
     Ok(new_index)
 }
 
+/// Global index of `asset` as of `now`, without writing it back.
+pub fn accrued_global_index(asset: &BorrowAssetInfo, now: u64) -> Result<u128> {
+    let utilization = calculate_utilization(asset.total_borrows, asset.total_deposits);
+    let borrow_rate = calculate_borrow_rate(
+        utilization,
+        asset.base_rate,
+        asset.optimal_utilization,
+        asset.slope1,
+        asset.slope2
+    );
+    let annual_rate = calculate_annual_rate_from_borrow_rate(borrow_rate);
+    update_global_index(asset.global_index, annual_rate, asset.last_update_ts, now)
+}
+
+/// Accrues `asset` up to `now` and returns its new global index.
+pub fn accrue_global_index(asset: &mut BorrowAssetInfo, now: u64) -> Result<u128> {
+    asset.global_index = accrued_global_index(asset, now)?;
+    asset.last_update_ts = now;
+    Ok(asset.global_index)
+}
+
 pub fn calculate_owed_amount(
     principal: u64,
     snapshot_index: u128,
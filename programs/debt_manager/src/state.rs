@@ -1,4 +1,5 @@
 use anchor_lang::prelude::*;
+use crate::health::HealthReport;
 
 #[account]
 pub struct ProtocolConfig {
@@ -6,9 +7,42 @@ pub struct ProtocolConfig {
     pub treasury: Pubkey,
     pub supported_collaterals: Vec<CollateralInfo>,
     pub supported_borrows: Vec<BorrowAssetInfo>,
+    pub liquidation_params: LiquidationParams,
+    /// Vaults with a `flash_loan` callback in progress. Nothing may move
+    /// tokens in or out of them until the loan has been checked.
+    pub locked_vaults: Vec<Pubkey>,
+    /// Callback programs that take flash loans without a fee.
+    pub fee_exempt_programs: Vec<Pubkey>,
+    /// Swap router programs `jupiter_swap` may call.
+    pub swap_programs: Vec<Pubkey>,
     pub bump: u8,
 }
 
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug)]
+pub struct LiquidationParams {
+    /// Bonus, in basis points, paid when an account has only just become
+    /// liquidatable. Capped by each collateral's `liquidation_bonus`.
+    pub min_liquidation_bonus: u64,
+    /// Seconds over which the bonus ramps from `min_liquidation_bonus` up to
+    /// the collateral's `liquidation_bonus`. Zero pays the full bonus at once.
+    pub bonus_ramp_duration: u64,
+    /// Share of a debt, in basis points, one liquidation may repay.
+    pub close_factor: u64,
+    /// Health factor, scaled by `HEALTH_FACTOR_SCALE`, below which a debt may
+    /// be repaid in full. Partial liquidations start to lower the health
+    /// factor under roughly `(1 + bonus) * liquidation_threshold`, so this
+    /// should sit above that; below it a liquidation that would lower health
+    /// falls back to the full close factor anyway. Must be in
+    /// `(0, HEALTH_FACTOR_SCALE]`.
+    pub full_liquidation_health_factor: u64,
+    /// Debt value, in `amount * price` units, at or below which a debt may be
+    /// repaid in full instead of leaving dust behind.
+    pub dust_debt_value: u128,
+    /// Share of the liquidation bonus, in basis points, paid to the treasury
+    /// instead of the liquidator.
+    pub protocol_fee_bps: u64,
+}
+
 #[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug)]
 pub struct CollateralInfo {
     pub mint: Pubkey,
@@ -16,6 +50,11 @@ pub struct CollateralInfo {
     pub liquidation_threshold: u64,
     pub liquidation_bonus: u64,
     pub price_feed: Pubkey,
+    /// Flash loan fee, in basis points, on loans out of this collateral's
+    /// vault.
+    pub flash_loan_fee_bps: u64,
+    /// Flash loan fees kept in the vault on top of user deposits.
+    pub reserves: u64,
 }
 
 #[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug)]
@@ -30,6 +69,15 @@ pub struct BorrowAssetInfo {
     pub global_index: u128,
     pub last_update_ts: u64,
     pub price_feed: Pubkey,
+    /// Protocol-owned buffer that absorbs bad debt ahead of the insurance
+    /// fund.
+    pub reserves: u64,
+    /// Cumulative debt written off by `write_off_bad_debt`.
+    pub bad_debt: u64,
+    /// Flash loan fee in basis points.
+    pub flash_loan_fee_bps: u64,
+    /// Most that `flash_mint` may mint at once; zero disables flash mints.
+    pub flash_mint_ceiling: u64,
 }
 
 #[account]
@@ -41,6 +89,16 @@ pub struct UserDebtAccount {
     pub last_points_update_ts: i64,
     pub referrer: Option<Pubkey>,
     pub bump: u8,
+    /// Health computed by the last full valuation, trusted by borrow and
+    /// withdraw while `health_refresh_slot` is the current slot.
+    pub cached_health: HealthReport,
+    pub health_refresh_slot: u64,
+    /// When the account was first seen liquidatable, 0 while healthy. Drives
+    /// the liquidation bonus ramp.
+    pub unhealthy_since_ts: i64,
+    /// Another wallet acting for the owner. Like the owner, it may not
+    /// liquidate this account.
+    pub delegate: Option<Pubkey>,
 }
 
 #[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug)]
@@ -56,8 +114,125 @@ pub struct DebtBalance {
     pub interest_index_snapshot: u128,
 }
 
+#[account]
+pub struct InsuranceFund {
+    pub mint: Pubkey,
+    pub total_shares: u64,
+    /// Seconds a staker must wait after `request_insurance_unstake`.
+    pub cooldown_seconds: u64,
+    /// Seconds after the cooldown during which the unstake may be made;
+    /// after that the request lapses and must be made again.
+    pub unstake_window_seconds: u64,
+    /// Largest share of the fund, in basis points, a single bad-debt
+    /// write-off may slash.
+    pub max_slash_bps: u64,
+    /// Share of flash loan fees, in basis points, routed to the fund.
+    pub fee_share_bps: u64,
+    /// Bumped when a slash empties the vault while shares are outstanding.
+    /// Stakes from an earlier epoch hold no shares.
+    pub share_epoch: u64,
+    pub bump: u8,
+}
+
+#[account]
+pub struct InsuranceStake {
+    pub owner: Pubkey,
+    pub mint: Pubkey,
+    pub shares: u64,
+    /// When the current cooldown started, 0 if none is pending.
+    pub cooldown_start_ts: i64,
+    /// `InsuranceFund::share_epoch` that `shares` were minted in.
+    pub share_epoch: u64,
+    pub bump: u8,
+}
+
 #[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug)]
 pub struct CollateralToSeize {
     pub mint: Pubkey,
     pub max_amount: u64,
 }
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug)]
+pub struct LiquidationRequest {
+    pub user: Pubkey,
+    pub borrow_mint: Pubkey,
+    pub collateral_mint: Pubkey,
+    pub amount: u64,
+}
+
+#[cfg(test)]
+pub mod test_fixtures {
+    use super::*;
+    use crate::math::INDEX_SCALE;
+
+    /// A borrow asset with no balances; tests set the fields they exercise.
+    pub fn borrow_asset() -> BorrowAssetInfo {
+        BorrowAssetInfo {
+            mint: Pubkey::default(),
+            base_rate: 0,
+            optimal_utilization: 8000,
+            slope1: 0,
+            slope2: 0,
+            total_deposits: 0,
+            total_borrows: 0,
+            global_index: INDEX_SCALE,
+            last_update_ts: 0,
+            price_feed: Pubkey::default(),
+            reserves: 0,
+            bad_debt: 0,
+            flash_loan_fee_bps: crate::instructions::flashloan::DEFAULT_FLASH_LOAN_FEE_BPS,
+            flash_mint_ceiling: 0,
+        }
+    }
+
+    /// Collateral at 75% LTV and an 80% liquidation threshold with a 10%
+    /// bonus.
+    pub fn collateral_info(mint: Pubkey, price_feed: Pubkey) -> CollateralInfo {
+        CollateralInfo {
+            mint,
+            ltv: 7500,
+            liquidation_threshold: 8000,
+            liquidation_bonus: 1000,
+            price_feed,
+            flash_loan_fee_bps: crate::instructions::flashloan::DEFAULT_FLASH_LOAN_FEE_BPS,
+            reserves: 0,
+        }
+    }
+
+    /// Pyth price account data quoting `price` at exponent 0, published at
+    /// `timestamp`.
+    pub fn price_account_data(price: i64, timestamp: i64) -> Vec<u8> {
+        use pyth_sdk_solana::state::{AccountType, PriceStatus, SolanaPriceAccount, MAGIC, VERSION_2};
+        let mut account = SolanaPriceAccount {
+            magic: MAGIC,
+            ver: VERSION_2,
+            atype: AccountType::Price as u32,
+            timestamp,
+            ..Default::default()
+        };
+        account.agg.price = price;
+        account.agg.status = PriceStatus::Trading;
+        bytemuck::bytes_of(&account).to_vec()
+    }
+
+    pub fn protocol_config(supported_borrows: Vec<BorrowAssetInfo>) -> ProtocolConfig {
+        ProtocolConfig {
+            admin: Pubkey::default(),
+            treasury: Pubkey::default(),
+            supported_collaterals: Vec::new(),
+            supported_borrows,
+            liquidation_params: LiquidationParams {
+                min_liquidation_bonus: 0,
+                bonus_ramp_duration: 0,
+                close_factor: crate::instructions::liquidation::DEFAULT_CLOSE_FACTOR,
+                full_liquidation_health_factor: crate::instructions::liquidation::DEFAULT_FULL_LIQUIDATION_HEALTH_FACTOR,
+                dust_debt_value: 0,
+                protocol_fee_bps: 0,
+            },
+            locked_vaults: Vec::new(),
+            fee_exempt_programs: Vec::new(),
+            swap_programs: Vec::new(),
+            bump: 0,
+        }
+    }
+}
@@ -0,0 +1,218 @@
+//! Account fixtures for running instructions' account validation off-chain.
+//! Accounts are leaked so they live for `'static`, as Anchor's
+//! `try_accounts` needs. CPIs cannot run outside the runtime, so handlers'
+//! swap CPIs are made through `invoke_mock_amm` and other token movements
+//! are applied with `transfer` and `mint_to`.
+#![allow(dead_code)]
+
+use anchor_lang::prelude::*;
+use anchor_lang::solana_program::entrypoint::ProgramResult;
+use anchor_lang::solana_program::instruction::Instruction;
+use anchor_lang::solana_program::program_option::COption;
+use anchor_lang::solana_program::program_pack::Pack;
+use anchor_spl::token::spl_token;
+
+pub fn account(key: Pubkey, owner: Pubkey, data: Vec<u8>) -> AccountInfo<'static> {
+    AccountInfo::new(
+        Box::leak(Box::new(key)),
+        false,
+        true,
+        Box::leak(Box::new(1_000_000_000)),
+        Box::leak(data.into_boxed_slice()),
+        Box::leak(Box::new(owner)),
+        false,
+        0,
+    )
+}
+
+pub fn signer(key: Pubkey) -> AccountInfo<'static> {
+    let mut info = account(key, System::id(), Vec::new());
+    info.is_signer = true;
+    info
+}
+
+pub fn program(id: Pubkey) -> AccountInfo<'static> {
+    let mut info = account(id, Pubkey::default(), Vec::new());
+    info.is_writable = false;
+    info.executable = true;
+    info
+}
+
+pub fn readonly(mut info: AccountInfo<'static>) -> AccountInfo<'static> {
+    info.is_writable = false;
+    info
+}
+
+/// An Anchor account's data, zero-padded to `space`.
+pub fn anchor_data<T: AccountSerialize>(account: &T, space: usize) -> Vec<u8> {
+    let mut data = Vec::new();
+    account.try_serialize(&mut data).unwrap();
+    assert!(data.len() <= space);
+    data.resize(space, 0);
+    data
+}
+
+pub fn mint(key: Pubkey, authority: Pubkey) -> AccountInfo<'static> {
+    let mint = spl_token::state::Mint {
+        mint_authority: COption::Some(authority),
+        supply: 0,
+        decimals: 6,
+        is_initialized: true,
+        freeze_authority: COption::None,
+    };
+    let mut data = vec![0; spl_token::state::Mint::LEN];
+    mint.pack_into_slice(&mut data);
+    account(key, spl_token::ID, data)
+}
+
+pub fn token_account(key: Pubkey, mint: Pubkey, owner: Pubkey, amount: u64) -> AccountInfo<'static> {
+    let state = spl_token::state::Account {
+        mint,
+        owner,
+        amount,
+        state: spl_token::state::AccountState::Initialized,
+        ..Default::default()
+    };
+    let mut data = vec![0; spl_token::state::Account::LEN];
+    state.pack_into_slice(&mut data);
+    account(key, spl_token::ID, data)
+}
+
+pub fn token_balance(info: &AccountInfo) -> u64 {
+    spl_token::state::Account::unpack(&info.data.borrow()).unwrap().amount
+}
+
+/// Moves `amount` between two token accounts, as the token program would.
+pub fn transfer<'a>(from: &AccountInfo<'a>, to: &AccountInfo<'a>, amount: u64) {
+    for (info, delta) in [(from, -(amount as i128)), (to, amount as i128)] {
+        let mut state = spl_token::state::Account::unpack(&info.data.borrow()).unwrap();
+        state.amount = (state.amount as i128 + delta) as u64;
+        state.pack_into_slice(&mut info.data.borrow_mut());
+    }
+}
+
+/// Credits `amount` to a token account, as `mint_to` would.
+pub fn mint_to(info: &AccountInfo, amount: u64) {
+    let mut state = spl_token::state::Account::unpack(&info.data.borrow()).unwrap();
+    state.amount += amount;
+    state.pack_into_slice(&mut info.data.borrow_mut());
+}
+
+/// A `mock_amm` pool between `mint_a` and `mint_b` holding `reserve` of each.
+pub struct Pool {
+    pub pool: AccountInfo<'static>,
+    pub vault_a: AccountInfo<'static>,
+    pub vault_b: AccountInfo<'static>,
+    pub fee_bps: u64,
+}
+
+pub fn pool(mint_a: Pubkey, mint_b: Pubkey, reserve: u64, fee_bps: u64) -> Pool {
+    let (key, bump) = Pubkey::find_program_address(&[b"pool", mint_a.as_ref(), mint_b.as_ref()], &mock_amm::ID);
+    let state = mock_amm::Pool { mint_a, mint_b, fee_bps, bump };
+    Pool {
+        pool: readonly(account(key, mock_amm::ID, anchor_data(&state, 8 + 32 + 32 + 8 + 1))),
+        vault_a: token_account(mock_amm::pool_vault(&key, &mint_a), mint_a, key, reserve),
+        vault_b: token_account(mock_amm::pool_vault(&key, &mint_b), mint_b, key, reserve),
+        fee_bps,
+    }
+}
+
+impl Pool {
+    /// `mock_amm::swap`'s accounts for swapping `source` for `destination`,
+    /// `a_to_b` selecting which of the pool's vaults receives the input.
+    pub fn swap_accounts(
+        &self,
+        user: &AccountInfo<'static>,
+        source: &AccountInfo<'static>,
+        destination: &AccountInfo<'static>,
+        a_to_b: bool,
+    ) -> Vec<AccountInfo<'static>> {
+        let (vault_in, vault_out) = if a_to_b { (&self.vault_a, &self.vault_b) } else { (&self.vault_b, &self.vault_a) };
+        vec![
+            self.pool.clone(),
+            user.clone(),
+            source.clone(),
+            destination.clone(),
+            vault_in.clone(),
+            vault_out.clone(),
+            program(anchor_spl::token::ID),
+        ]
+    }
+}
+
+/// Runs `mock_amm::swap` against `accounts`, decoding `data` the way its
+/// entrypoint would and validating the accounts with its `Swap` struct, then
+/// applies the resulting transfers. Returns the amount paid out.
+pub fn execute_mock_amm_swap(program_id: &Pubkey, accounts: &[AccountInfo<'static>], data: &[u8]) -> u64 {
+    use anchor_lang::{Discriminator, InstructionData};
+
+    assert_eq!(*program_id, mock_amm::ID);
+    assert_eq!(data[..8], *mock_amm::instruction::Swap::DISCRIMINATOR);
+    let args = mock_amm::instruction::Swap::deserialize(&mut &data[8..]).unwrap();
+    assert_eq!(args.data(), data);
+
+    let accounts: &'static [AccountInfo<'static>] = Box::leak(accounts.to_vec().into_boxed_slice());
+    let mut remaining = accounts;
+    let swap = mock_amm::Swap::try_accounts(
+        &mock_amm::ID,
+        &mut remaining,
+        data,
+        &mut Default::default(),
+        &mut Default::default(),
+    )
+    .unwrap();
+    assert!(remaining.is_empty());
+
+    let amount_out = mock_amm::swap_output(
+        args.amount_in,
+        swap.pool_source_vault.amount,
+        swap.pool_destination_vault.amount,
+        swap.pool.fee_bps,
+    )
+    .unwrap();
+    assert!(amount_out >= args.minimum_amount_out);
+    transfer(&accounts[2], &accounts[4], args.amount_in);
+    transfer(&accounts[5], &accounts[3], amount_out);
+    amount_out
+}
+
+/// `swap::SwapInvoke` that executes the instruction with
+/// `execute_mock_amm_swap`.
+pub fn invoke_mock_amm(instruction: &Instruction, accounts: &[AccountInfo<'static>]) -> ProgramResult {
+    assert_eq!(
+        instruction.accounts,
+        accounts.iter()
+            .map(|a| if a.is_writable { AccountMeta::new(a.key(), a.is_signer) } else { AccountMeta::new_readonly(a.key(), a.is_signer) })
+            .collect::<Vec<_>>()
+    );
+    execute_mock_amm_swap(&instruction.program_id, accounts, &instruction.data);
+    Ok(())
+}
+
+/// `ProtocolConfig` allowing `mock_amm` as a swap venue, with nothing listed.
+pub fn protocol_config() -> debt_manager::state::ProtocolConfig {
+    let (_, bump) = Pubkey::find_program_address(&[b"config"], &debt_manager::ID);
+    debt_manager::state::ProtocolConfig {
+        admin: Pubkey::default(),
+        treasury: Pubkey::default(),
+        supported_collaterals: Vec::new(),
+        supported_borrows: Vec::new(),
+        liquidation_params: debt_manager::state::LiquidationParams {
+            min_liquidation_bonus: 0,
+            bonus_ramp_duration: 0,
+            close_factor: debt_manager::instructions::DEFAULT_CLOSE_FACTOR,
+            full_liquidation_health_factor: debt_manager::instructions::DEFAULT_FULL_LIQUIDATION_HEALTH_FACTOR,
+            dust_debt_value: 0,
+            protocol_fee_bps: 0,
+        },
+        locked_vaults: Vec::new(),
+        fee_exempt_programs: Vec::new(),
+        swap_programs: vec![mock_amm::ID],
+        bump,
+    }
+}
+
+pub fn config_account(config: &debt_manager::state::ProtocolConfig) -> AccountInfo<'static> {
+    let key = Pubkey::find_program_address(&[b"config"], &debt_manager::ID).0;
+    account(key, debt_manager::ID, anchor_data(config, 4096))
+}
@@ -0,0 +1,121 @@
+mod common;
+
+use anchor_lang::prelude::*;
+use anchor_lang::InstructionData;
+use common::*;
+use debt_manager::errors::ErrorCode;
+use anchor_lang::solana_program::entrypoint::ProgramResult;
+use anchor_lang::solana_program::instruction::Instruction;
+use debt_manager::instructions::{leverage, swap, OpenLeveragedPosition, SwapRoute};
+use debt_manager::state::UserDebtAccount;
+
+const BORROW_AMOUNT: u64 = 1_000;
+/// Borrow asset the owner already held before the position was opened.
+const EXISTING_BALANCE: u64 = 300;
+
+struct Setup {
+    /// `open_leveraged_position`'s accounts followed by the venue's.
+    accounts: &'static [AccountInfo<'static>],
+}
+
+fn setup() -> Setup {
+    let (collateral_mint, borrow_mint) = (Pubkey::new_unique(), Pubkey::new_unique());
+    let owner = signer(Pubkey::new_unique());
+    let config = config_account(&protocol_config());
+
+    let (debt_key, debt_bump) = Pubkey::find_program_address(&[b"debt", owner.key.as_ref()], &debt_manager::ID);
+    let debt_account = UserDebtAccount {
+        owner: owner.key(),
+        collateral_balances: Vec::new(),
+        debt_balances: Vec::new(),
+        accumulated_points: 0,
+        last_points_update_ts: 0,
+        referrer: None,
+        bump: debt_bump,
+        cached_health: Default::default(),
+        health_refresh_slot: 0,
+        unhealthy_since_ts: 0,
+        delegate: None,
+    };
+    let debt_account = account(
+        debt_key,
+        debt_manager::ID,
+        anchor_data(&debt_account, debt_manager::instructions::user::DEBT_ACCOUNT_SPACE),
+    );
+
+    let vault_key = Pubkey::find_program_address(&[b"vault", collateral_mint.as_ref()], &debt_manager::ID).0;
+    let user_collateral = token_account(Pubkey::new_unique(), collateral_mint, owner.key(), 0);
+    let user_borrow_account = token_account(Pubkey::new_unique(), borrow_mint, owner.key(), EXISTING_BALANCE);
+    let pool = pool(borrow_mint, collateral_mint, 1_000_000, 30);
+
+    let mut accounts = vec![
+        debt_account,
+        owner.clone(),
+        user_collateral.clone(),
+        user_borrow_account.clone(),
+        token_account(vault_key, collateral_mint, vault_key, 0),
+        readonly(mint(collateral_mint, Pubkey::default())),
+        mint(borrow_mint, config.key()),
+        config,
+        program(anchor_spl::token::ID),
+        readonly(account(Pubkey::new_unique(), Pubkey::default(), Vec::new())),
+        readonly(account(Pubkey::new_unique(), Pubkey::default(), Vec::new())),
+        program(mock_amm::ID),
+    ];
+    accounts.extend(pool.swap_accounts(&owner, &user_borrow_account, &user_collateral, true));
+    Setup { accounts: Box::leak(accounts.into_boxed_slice()) }
+}
+
+/// Stands in for `token::mint_to` in `leverage::mint_and_swap`.
+fn mint_borrow(accounts: &OpenLeveragedPosition<'static>, amount: u64) -> Result<()> {
+    mint_to(&accounts.user_borrow_account.to_account_info(), amount);
+    Ok(())
+}
+
+/// A venue that swaps through `mock_amm` and then also takes the owner's
+/// existing balance.
+fn greedy_venue(instruction: &Instruction, accounts: &[AccountInfo<'static>]) -> ProgramResult {
+    invoke_mock_amm(instruction, accounts)?;
+    transfer(&accounts[2], &accounts[4], EXISTING_BALANCE);
+    Ok(())
+}
+
+/// Validates the accounts as `OpenLeveragedPosition` and runs the handler's
+/// mint and swap of `BORROW_AMOUNT`, with the venue CPI made through
+/// `invoke_swap`.
+fn mint_and_swap(setup: &Setup, invoke_swap: swap::SwapInvoke<'static>) -> Result<swap::SwapResult> {
+    let mut accounts = setup.accounts;
+    let mut ctx = OpenLeveragedPosition::try_accounts(
+        &debt_manager::ID,
+        &mut accounts,
+        &debt_manager::instruction::OpenLeveragedPosition {
+            initial_amount: 0,
+            target_leverage: 0,
+            min_out: 0,
+            route: SwapRoute::ConstantProduct,
+            feed_count: 0,
+        }
+        .data(),
+        &mut Default::default(),
+        &mut Default::default(),
+    )?;
+    leverage::mint_and_swap(&mut ctx, accounts, &SwapRoute::ConstantProduct, BORROW_AMOUNT, 0, mint_borrow, invoke_swap)
+}
+
+#[test]
+fn leveraged_swap_spends_exactly_the_mint() {
+    let setup = setup();
+    let result = mint_and_swap(&setup, invoke_mock_amm).unwrap();
+    assert_eq!(result.amount_in, BORROW_AMOUNT);
+    assert_eq!(token_balance(&setup.accounts[3]), EXISTING_BALANCE);
+}
+
+#[test]
+fn leveraged_swap_rejects_spending_the_existing_balance() {
+    // Against a snapshot taken before the mint, taking the owner's existing
+    // balance on top of the mint would look like spending only
+    // `EXISTING_BALANCE`.
+    let setup = setup();
+    let err = mint_and_swap(&setup, greedy_venue).unwrap_err();
+    assert_eq!(err, ErrorCode::SlippageExceeded.into());
+}
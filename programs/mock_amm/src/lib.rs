@@ -0,0 +1,168 @@
+use anchor_lang::prelude::*;
+use anchor_spl::token::{self, Mint, Token, TokenAccount, Transfer};
+
+declare_id!("MYdZjNUL5yxGXAsJseh1z2488ddx7dePafiiJwBoP8N");
+
+/// Minimal constant-product pool. Its `swap(amount_in, minimum_amount_out)`
+/// instruction is the interface `debt_manager`'s constant-product adapter
+/// targets.
+#[program]
+pub mod mock_amm {
+    use super::*;
+
+    pub fn initialize_pool(ctx: Context<InitializePool>, fee_bps: u64) -> Result<()> {
+        if fee_bps >= 10000 {
+            return Err(AmmError::InvalidFee.into());
+        }
+        let pool = &mut ctx.accounts.pool;
+        pool.mint_a = ctx.accounts.mint_a.key();
+        pool.mint_b = ctx.accounts.mint_b.key();
+        pool.fee_bps = fee_bps;
+        pool.bump = ctx.bumps.pool;
+        Ok(())
+    }
+
+    /// Swaps exactly `amount_in` of the source vault's mint for the other.
+    pub fn swap(ctx: Context<Swap>, amount_in: u64, minimum_amount_out: u64) -> Result<()> {
+        let pool = &ctx.accounts.pool;
+        let source_vault = &ctx.accounts.pool_source_vault;
+        let destination_vault = &ctx.accounts.pool_destination_vault;
+        let vaults = [pool_vault(&pool.key(), &pool.mint_a), pool_vault(&pool.key(), &pool.mint_b)];
+        if !vaults.contains(&source_vault.key())
+            || !vaults.contains(&destination_vault.key())
+            || source_vault.key() == destination_vault.key()
+        {
+            return Err(AmmError::InvalidVault.into());
+        }
+
+        let amount_out = swap_output(amount_in, source_vault.amount, destination_vault.amount, pool.fee_bps)
+            .ok_or(AmmError::MathOverflow)?;
+        if amount_out < minimum_amount_out {
+            return Err(AmmError::SlippageExceeded.into());
+        }
+
+        let cpi_accounts = Transfer {
+            from: ctx.accounts.user_source.to_account_info(),
+            to: source_vault.to_account_info(),
+            authority: ctx.accounts.user.to_account_info(),
+        };
+        let cpi_ctx = CpiContext::new(ctx.accounts.token_program.to_account_info(), cpi_accounts);
+        token::transfer(cpi_ctx, amount_in)?;
+
+        let seeds = &[
+            b"pool".as_ref(),
+            pool.mint_a.as_ref(),
+            pool.mint_b.as_ref(),
+            &[pool.bump],
+        ];
+        let signer = &[&seeds[..]];
+        let cpi_accounts = Transfer {
+            from: destination_vault.to_account_info(),
+            to: ctx.accounts.user_destination.to_account_info(),
+            authority: ctx.accounts.pool.to_account_info(),
+        };
+        let cpi_ctx = CpiContext::new_with_signer(ctx.accounts.token_program.to_account_info(), cpi_accounts, signer);
+        token::transfer(cpi_ctx, amount_out)
+    }
+}
+
+#[account]
+pub struct Pool {
+    pub mint_a: Pubkey,
+    pub mint_b: Pubkey,
+    pub fee_bps: u64,
+    pub bump: u8,
+}
+
+/// Address of `pool`'s token account for `mint`.
+pub fn pool_vault(pool: &Pubkey, mint: &Pubkey) -> Pubkey {
+    Pubkey::find_program_address(&[b"pool_vault", pool.as_ref(), mint.as_ref()], &crate::ID).0
+}
+
+/// Output of a constant-product swap after the input fee.
+pub fn swap_output(amount_in: u64, reserve_in: u64, reserve_out: u64, fee_bps: u64) -> Option<u64> {
+    let amount_in = (amount_in as u128).checked_mul(10000 - fee_bps as u128)? / 10000;
+    let denominator = (reserve_in as u128).checked_add(amount_in)?;
+    if denominator == 0 {
+        return Some(0);
+    }
+    Some((amount_in.checked_mul(reserve_out as u128)? / denominator) as u64)
+}
+
+#[derive(Accounts)]
+pub struct InitializePool<'info> {
+    #[account(
+        init,
+        payer = payer,
+        space = 8 + 32 + 32 + 8 + 1,
+        seeds = [b"pool", mint_a.key().as_ref(), mint_b.key().as_ref()],
+        bump
+    )]
+    pub pool: Account<'info, Pool>,
+    pub mint_a: Account<'info, Mint>,
+    pub mint_b: Account<'info, Mint>,
+    #[account(
+        init,
+        payer = payer,
+        seeds = [b"pool_vault", pool.key().as_ref(), mint_a.key().as_ref()],
+        bump,
+        token::mint = mint_a,
+        token::authority = pool
+    )]
+    pub vault_a: Account<'info, TokenAccount>,
+    #[account(
+        init,
+        payer = payer,
+        seeds = [b"pool_vault", pool.key().as_ref(), mint_b.key().as_ref()],
+        bump,
+        token::mint = mint_b,
+        token::authority = pool
+    )]
+    pub vault_b: Account<'info, TokenAccount>,
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    pub system_program: Program<'info, System>,
+    pub token_program: Program<'info, Token>,
+    pub rent: Sysvar<'info, Rent>,
+}
+
+#[derive(Accounts)]
+pub struct Swap<'info> {
+    pub pool: Account<'info, Pool>,
+    pub user: Signer<'info>,
+    #[account(mut)]
+    pub user_source: Account<'info, TokenAccount>,
+    #[account(mut)]
+    pub user_destination: Account<'info, TokenAccount>,
+    #[account(mut)]
+    pub pool_source_vault: Account<'info, TokenAccount>,
+    #[account(mut)]
+    pub pool_destination_vault: Account<'info, TokenAccount>,
+    pub token_program: Program<'info, Token>,
+}
+
+#[error_code]
+pub enum AmmError {
+    #[msg("Fee must be below 100%")]
+    InvalidFee,
+    #[msg("Not one of the pool's vaults")]
+    InvalidVault,
+    #[msg("Output below minimum")]
+    SlippageExceeded,
+    #[msg("Math overflow")]
+    MathOverflow,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_swap_output() {
+        // 1:1 pool of 1000 each, 100 in without fee: 100 * 1000 / 1100.
+        assert_eq!(swap_output(100, 1_000, 1_000, 0), Some(90));
+        // 30 bps fee leaves 99.7 effective input.
+        assert_eq!(swap_output(1_000, 1_000_000, 1_000_000, 30), Some(996));
+        assert_eq!(swap_output(0, 0, 0, 0), Some(0));
+    }
+}